@@ -1,81 +1,328 @@
-pub mod ansi {
-    use std::fmt;
-    use std::io::Write;
-
-    const CSI: &str = "\x1b[";
-
-    pub enum Erase {
-        CURSOR_TO_END,
-        CURSOR_TO_BEGIN,
-        SCREEN,
-        SCREEN_AND_DELETE
-    }
-    
-    pub fn erase<W: Write>(mode: Erase, writer: &mut W) -> std::io::Result<()> {
-        let n = match mode {
-            Erase::CURSOR_TO_END => 0,
-            Erase::CURSOR_TO_BEGIN => 1,
-            Erase::SCREEN => 2,
-            Erase::SCREEN_AND_DELETE => 3
-        };
-            
-        write!(writer, "{CSI}{n}J")
-    }
-
-    #[derive(Copy, Clone, PartialEq)]
-    pub struct Color {
-        pub red: u8,
-        pub green: u8,
-        pub blue: u8
-    }
-
-    impl fmt::Debug for Color {
-        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "r/g/b: {}/{}/{}", self.red, self.green, self.blue)
-        }
-    }
-
-    impl From<u32> for Color {
-        fn from(value: u32) -> Self {
-            let red = ((value >> 16) & 0xff) as u8;
-            let green = ((value >> 8) & 0xff) as u8;
-            let blue = (value & 0xff) as u8;
-            Color {red, green, blue}
-        }
-    }
-
-    impl Color {
-        pub fn print<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
-            set_foreground_color(writer, '█', self.to_string())
-        }
-
-        fn to_string(&self) -> String {
-            format!("{};{};{}", self.red, self.green, self.blue)
-        }
-    }
-
-    fn set_foreground_color<W: Write>(writer: &mut W, character: char, color: String) -> std::io::Result<()> {
-        write!(writer, "{CSI}38;2;{color}m{character}{CSI}m")
-    }
-
-    pub struct CursorPos {
-        pub x: usize,
-        pub y: usize
-    }
-    
-    pub fn reset_cursor<W: Write>(writer: &mut W) -> std::io::Result<()> {
-        set_cursor(CursorPos {x: 1, y: 1}, writer)
-    }
-
-    pub fn set_cursor<W: Write>(pos: CursorPos, writer: &mut W) -> std::io::Result<()> {
-        write!(writer, "{CSI}{};{}H", pos.y, pos.x)
-    }
-        
-    pub fn set_horizontal<W: Write>(x: usize, writer: &mut W) -> std::io::Result<()> {
-        write!(writer, "{CSI}{}G", x)
-    }
-        
-    pub fn next_line<W: Write>(writer: &mut W) -> std::io::Result<()> {
-        write!(writer, "{CSI}1E")
-    }
-}
\ No newline at end of file
+pub mod ansi {
+    use std::env;
+    use std::fmt;
+    use std::io::{stdout, Error, Write};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::common::common::base64_encode;
+
+    const CSI: &str = "\x1b[";
+
+    #[allow(dead_code)]
+    pub enum Erase {
+        CursorToEnd,
+        CursorToBegin,
+        Screen,
+        ScreenAndDelete
+    }
+    
+    pub fn erase<W: Write>(mode: Erase, writer: &mut W) -> std::io::Result<()> {
+        let n = match mode {
+            Erase::CursorToEnd => 0,
+            Erase::CursorToBegin => 1,
+            Erase::Screen => 2,
+            Erase::ScreenAndDelete => 3
+        };
+            
+        write!(writer, "{CSI}{n}J")
+    }
+
+    #[derive(Copy, Clone, PartialEq)]
+    pub enum ColorMode {
+        TrueColor,
+        // xterm palette of a 6x6x6 color cube and a 24 step gray ramp
+        Ansi256
+    }
+
+    // Terminals announce 24 bit support through COLORTERM, some only through their TERM name
+    pub fn detect_color_mode() -> ColorMode {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") || term.contains("truecolor") {
+            ColorMode::TrueColor
+        } else {
+            ColorMode::Ansi256
+        }
+    }
+
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    #[derive(Copy, Clone, PartialEq)]
+    pub struct Color {
+        pub red: u8,
+        pub green: u8,
+        pub blue: u8,
+        pub alpha: u8
+    }
+
+    impl fmt::Debug for Color {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "r/g/b/a: {}/{}/{}/{}", self.red, self.green, self.blue, self.alpha)
+        }
+    }
+
+    // The top byte is reserved in color tables and BI_RGB pixels, so colors are opaque
+    impl From<u32> for Color {
+        fn from(value: u32) -> Self {
+            let red = ((value >> 16) & 0xff) as u8;
+            let green = ((value >> 8) & 0xff) as u8;
+            let blue = (value & 0xff) as u8;
+            Color {red, green, blue, alpha: 255}
+        }
+    }
+
+    impl Color {
+        pub fn print<W: Write>(&self, mode: ColorMode, writer: &mut W) -> std::io::Result<()> {
+            self.print_char('█', mode, writer)
+        }
+
+        pub fn print_char<W: Write>(&self, character: char, mode: ColorMode, writer: &mut W) -> std::io::Result<()> {
+            set_foreground_color(writer, character, self.to_ansi(mode))
+        }
+
+        // Fills the cells behind text, which keeps the terminal's default foreground color
+        pub fn print_bg<W: Write>(&self, text: &str, mode: ColorMode, writer: &mut W) -> std::io::Result<()> {
+            set_background_color(writer, text, self.to_ansi(mode))
+        }
+
+        // ITU-R BT.601 weights
+        pub fn luma(self) -> u8 {
+            (0.299 * self.red as f64 + 0.587 * self.green as f64 + 0.114 * self.blue as f64).round() as u8
+        }
+
+        // Contrast scales the distance of each channel from mid-gray, brightness is added afterwards
+        pub fn adjust(self, brightness: f64, contrast: f64) -> Color {
+            let channel = |value: u8| ((value as f64 - 128.0) * contrast + 128.0 + brightness).round().clamp(0.0, 255.0) as u8;
+            Color {red: channel(self.red), green: channel(self.green), blue: channel(self.blue), alpha: self.alpha}
+        }
+
+        pub fn apply_gamma(self, gamma: f64) -> Color {
+            let channel = |value: u8| (255.0 * (value as f64 / 255.0).powf(1.0 / gamma)).round() as u8;
+            Color {red: channel(self.red), green: channel(self.green), blue: channel(self.blue), alpha: self.alpha}
+        }
+
+        // Linear interpolation, t = 0 is a and t = 1 is b
+        pub fn blend(a: Color, b: Color, t: f64) -> Color {
+            let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+            Color {red: channel(a.red, b.red), green: channel(a.green, b.green), blue: channel(a.blue, b.blue), alpha: channel(a.alpha, b.alpha)}
+        }
+
+        // out = fg * a + bg * (1 - a), the result is opaque
+        pub fn composite_over(self, background: Color) -> Color {
+            if self.alpha == 255 {
+                return self;
+            }
+            let alpha = self.alpha as u32;
+            let channel = |fg: u8, bg: u8| ((fg as u32 * alpha + bg as u32 * (255 - alpha) + 127) / 255) as u8;
+            Color {red: channel(self.red, background.red), green: channel(self.green, background.green), blue: channel(self.blue, background.blue), alpha: 255}
+        }
+
+        // out = fg + bg * (1 - a), for colors whose channels are already multiplied by their alpha
+        pub fn composite_premultiplied_over(self, background: Color) -> Color {
+            if self.alpha == 255 {
+                return self;
+            }
+            let alpha = self.alpha as u32;
+            let channel = |fg: u8, bg: u8| std::cmp::min(fg as u32 + (bg as u32 * (255 - alpha) + 127) / 255, 255) as u8;
+            Color {red: channel(self.red, background.red), green: channel(self.green, background.green), blue: channel(self.blue, background.blue), alpha: 255}
+        }
+
+        // Divides premultiplied channels by the alpha, giving the straight color composite_over expects
+        pub fn unpremultiply(self) -> Color {
+            if self.alpha == 0 || self.alpha == 255 {
+                return self;
+            }
+            let alpha = self.alpha as u32;
+            let channel = |value: u8| std::cmp::min((value as u32 * 255 + alpha / 2) / alpha, 255) as u8;
+            Color {red: channel(self.red), green: channel(self.green), blue: channel(self.blue), alpha: self.alpha}
+        }
+
+        pub fn to_grayscale(self) -> Color {
+            let luma = self.luma();
+            Color {red: luma, green: luma, blue: luma, alpha: self.alpha}
+        }
+
+        // Accepts #rgb and #rrggbb, the leading '#' is optional
+        pub fn from_hex(hex: &str) -> std::io::Result<Color> {
+            let digits = hex.strip_prefix('#').unwrap_or(hex);
+            if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(Error::other(format!("Invalid hex color {hex}")));
+            }
+
+            let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| Error::other(format!("Invalid hex color {hex}")));
+            let (red, green, blue) = match digits.len() {
+                // #abc is shorthand for #aabbcc
+                3 => (channel(&digits[0..1])? * 17, channel(&digits[1..2])? * 17, channel(&digits[2..3])? * 17),
+                6 => (channel(&digits[0..2])?, channel(&digits[2..4])?, channel(&digits[4..6])?),
+                _ => return Err(Error::other(format!("Hex color {hex} must have 3 or 6 digits")))
+            };
+
+            Ok(Color {red, green, blue, alpha: 255})
+        }
+
+        pub fn to_hex(self) -> String {
+            format!("#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+        }
+
+        fn to_ansi(self, mode: ColorMode) -> String {
+            match mode {
+                ColorMode::TrueColor => format!("2;{};{};{}", self.red, self.green, self.blue),
+                ColorMode::Ansi256 => format!("5;{}", self.to_ansi256())
+            }
+        }
+
+        // Closest of the nearest color cube entry and the nearest gray ramp entry. The 256 colors are the
+        // terminal's own, so unlike Sixel, which defines its palette, this can't use a median-cut palette
+        fn to_ansi256(self) -> u8 {
+            let nearest_level = |value: u8| (0..CUBE_LEVELS.len()).min_by_key(|&i| CUBE_LEVELS[i].abs_diff(value)).unwrap_or(0);
+            let (red, green, blue) = (nearest_level(self.red), nearest_level(self.green), nearest_level(self.blue));
+            let cube = Color {red: CUBE_LEVELS[red], green: CUBE_LEVELS[green], blue: CUBE_LEVELS[blue], alpha: 255};
+
+            let average = (self.red as usize + self.green as usize + self.blue as usize) / 3;
+            let gray_step = std::cmp::min(average.saturating_sub(3) / 10, 23);
+            let gray_value = (8 + 10 * gray_step) as u8;
+            let gray = Color {red: gray_value, green: gray_value, blue: gray_value, alpha: 255};
+
+            if self.distance(gray) < self.distance(cube) {
+                (232 + gray_step) as u8
+            } else {
+                (16 + 36 * red + 6 * green + blue) as u8
+            }
+        }
+
+        fn distance(self, other: Color) -> u32 {
+            let diff = |a: u8, b: u8| (a.abs_diff(b) as u32).pow(2);
+            diff(self.red, other.red) + diff(self.green, other.green) + diff(self.blue, other.blue)
+        }
+    }
+
+    // Precomputed Color::apply_gamma for the per-pixel loops
+    #[derive(Copy, Clone)]
+    pub struct GammaTable {
+        values: [u8; 256]
+    }
+
+    impl GammaTable {
+        pub fn new(gamma: f64) -> Self {
+            let mut values = [0; 256];
+            for (value, corrected) in values.iter_mut().enumerate() {
+                let gray = Color {red: value as u8, green: value as u8, blue: value as u8, alpha: 255};
+                *corrected = gray.apply_gamma(gamma).red;
+            }
+            GammaTable {values}
+        }
+
+        pub fn apply(&self, color: Color) -> Color {
+            Color {
+                red: self.values[color.red as usize],
+                green: self.values[color.green as usize],
+                blue: self.values[color.blue as usize],
+                alpha: color.alpha
+            }
+        }
+    }
+
+    fn set_foreground_color<W: Write>(writer: &mut W, character: char, color: String) -> std::io::Result<()> {
+        write!(writer, "{CSI}38;{color}m{character}{CSI}m")
+    }
+
+    fn set_background_color<W: Write>(writer: &mut W, text: &str, color: String) -> std::io::Result<()> {
+        write!(writer, "{CSI}48;{color}m{text}{CSI}m")
+    }
+
+    pub struct CursorPos {
+        pub x: usize,
+        pub y: usize
+    }
+    
+    pub fn reset_cursor<W: Write>(writer: &mut W) -> std::io::Result<()> {
+        set_cursor(CursorPos {x: 1, y: 1}, writer)
+    }
+
+    pub fn set_cursor<W: Write>(pos: CursorPos, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "{CSI}{};{}H", pos.y, pos.x)
+    }
+        
+    pub fn cursor_forward<W: Write>(n: usize, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "{CSI}{n}C")
+    }
+
+    // Blanks n cells from the cursor on, the cursor stays where it is
+    pub fn erase_characters<W: Write>(n: usize, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "{CSI}{n}X")
+    }
+
+    // Interactive views draw on the alternate screen, leaving it restores the shell's previous content
+    pub struct AlternateScreen;
+
+    // Lets the panic hook leave the alternate screen, so the message isn't wiped with it afterwards
+    static ALTERNATE_SCREEN_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+    impl AlternateScreen {
+        pub fn enter() -> std::io::Result<Self> {
+            let mut stdout = stdout();
+            write!(stdout, "{CSI}?1049h")?;
+            stdout.flush()?;
+            ALTERNATE_SCREEN_ACTIVE.store(true, Ordering::SeqCst);
+            Ok(AlternateScreen)
+        }
+    }
+
+    impl Drop for AlternateScreen {
+        fn drop(&mut self) {
+            leave_alternate_screen();
+        }
+    }
+
+    pub fn leave_alternate_screen() {
+        if ALTERNATE_SCREEN_ACTIVE.swap(false, Ordering::SeqCst) {
+            let mut stdout = stdout();
+            let _ = write!(stdout, "{CSI}?1049l");
+            let _ = stdout.flush();
+        }
+    }
+
+    pub fn hide_cursor<W: Write>(writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "{CSI}?25l")
+    }
+
+    pub fn show_cursor<W: Write>(writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "{CSI}?25h")
+    }
+
+    // OSC 52, supported by most terminal emulators and forwarded by tmux/ssh
+    pub fn copy_to_clipboard<W: Write>(text: &str, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "\x1b]52;c;{}\x07", base64_encode(text.as_bytes()))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn gamma_one_is_the_identity() {
+            let table = GammaTable::new(1.0);
+            for value in 0..=255 {
+                let color = Color {red: value, green: value, blue: value, alpha: 255};
+                assert_eq!(color.apply_gamma(1.0), color);
+                assert_eq!(table.apply(color), color);
+            }
+        }
+
+        #[test]
+        fn from_hex_short_long_and_without_hash() {
+            let color = Color {red: 0xaa, green: 0xbb, blue: 0xcc, alpha: 255};
+            assert_eq!(Color::from_hex("#abc").unwrap(), color);
+            assert_eq!(Color::from_hex("#aabbcc").unwrap(), color);
+            assert_eq!(Color::from_hex("AaBbCc").unwrap(), color);
+            assert_eq!(Color::from_hex("abc").unwrap(), color);
+        }
+
+        #[test]
+        fn from_hex_rejects_invalid_colors() {
+            for hex in ["#abg", "#12345z", "#+1234", "#", "#abcd", "##abc", "#ａbc"] {
+                assert!(Color::from_hex(hex).is_err(), "{hex}");
+            }
+        }
+    }
+}
@@ -0,0 +1,341 @@
+pub mod args {
+    use std::io::{stdin, Error, IsTerminal};
+    use std::time::Duration;
+
+    use crate::ansi::ansi::{detect_color_mode, Color, ColorMode, GammaTable};
+    use crate::bitmap::bitmap::{Flip, Orientation, Transition};
+    use crate::render::render::{Backend, CursorEnd, Inset, RenderOptions, RenderTarget, Sampling, ScaleMode};
+    use crate::settings::settings::Settings;
+
+    pub const USAGE: &str = "Usage: cargo run -- [--hex] [--histogram] [--verbose] [--stats] [--orient top-down|bottom-up|auto] [--missing-color HEX] [--flip h|v] [--rotate 90|180|270] [--crop X,Y,W,H] [--render terminal|null] [--output FILE] [--save FILE] [--pixelated|--smooth] [--scale fit|fill|stretch] [--sixel|--kitty|--braille|--ascii|--ascii-color] [--threshold N] [--ramp CHARS] [--fast N] [--cursor below|keep|home] [--inset TOP,RIGHT,BOTTOM,LEFT] [--color auto|truecolor|256] [--dither] [--grayscale] [--checkerboard] [--bg HEX] [--premultiplied] [--brightness N] [--contrast F] [--gamma G] [--fps N|--delay MS] [--transition fade|wipe] [--pan] [--grid] [--recursive] [--list] [--help] [dirname/filename/-]";
+
+    pub const HELP: &str = "Shows an image in the terminal, or all images of a directory as a slideshow.
+Without a path, or with a path of -, the image is read from stdin.
+Keys and the status bar color can be changed in ~/.config/term_viewer/config.toml.
+
+Options:
+  --hex                    Page through a hexdump of the file, which is also shown for files that can't be decoded
+  --histogram              Print red/green/blue/luma histograms of the image
+  --pan                    Fit the image to the terminal height and pan with w/a/s/d, zoom in and out with +/-
+  --grid                   Show the images of a directory as pages of thumbnails
+  --recursive              Include the images of all subdirectories, without following symlinked directories
+  --list                   Print the files that would be shown
+  --verbose                Print the parsed headers, size and PNG text of every decoded image to stderr
+  --stats                  Print decode time, render time and bytes written for every image to stderr
+  --orient MODE            Row order of BMP files: top-down, bottom-up or auto (default)
+  --missing-color HEX      Color of palette indices past the end of a BMP color table (default 000000)
+  --flip h|v               Mirror the image horizontally or vertically
+  --rotate DEGREES         Rotate the image clockwise by 90, 180 or 270 degrees, after flipping
+  --crop X,Y,W,H           Only show the W x H pixels starting at X,Y of the flipped and rotated image
+  --render TARGET          terminal (default), or null to discard the output
+  --output FILE            Write the escape sequences of the image into FILE instead of the terminal
+  --save FILE              Save the flipped, rotated, cropped and color adjusted image as a 24 bpp BMP file
+  --pixelated, --smooth    Force nearest neighbor or area averaged downscaling (slideshows default to nearest
+                           neighbor unless --fast is given)
+  --scale MODE             How block characters cover the terminal: fit (default) keeps the aspect ratio,
+                           fill also does but crops the overflow, stretch scales each axis on its own
+  --fast N                 Only sample every Nth source pixel
+  --sixel, --kitty         Draw with Sixel graphics or the kitty graphics protocol instead of block characters,
+                           if the terminal supports them
+  --braille                Draw in black and white with 2x4 Braille dots per cell
+  --threshold N            Luma from 0 to 255 at which Braille dots are set (default 128)
+  --ascii                  Draw with characters whose density follows the luma of their cell
+  --ascii-color            Like --ascii, with each character in the color of its cell
+  --ramp CHARS             Characters of --ascii from dark to bright (default \" .:-=+*#%@\")
+  --cursor POS             Where to leave the cursor: below (default), keep or home
+  --inset T,R,B,L          Keep cells at the terminal edges free
+  --color MODE             auto (default), truecolor or 256
+  --dither                 Smooth gradients with ordered dithering in 256 color mode
+  --grayscale              Show the image in shades of gray
+  --checkerboard           Draw a gray checkerboard where block character images are fully transparent
+  --bg HEX                 Background that transparent pixels are blended with (default 000000, with --kitty
+                           the terminal's own)
+  --premultiplied          Composite images whose colors are already multiplied by their alpha, TGA files that
+                           declare it are detected without the flag
+  --brightness N           Add N to every color channel, e.g. 40 or -40
+  --contrast F             Scale the distance of every color channel from mid-gray by F, e.g. 1.5
+  --gamma G                Gamma correct every color channel, values above 1 brighten the image
+  --fps N, --delay MS      Slideshow speed in frames per second or milliseconds per frame (default 33ms)
+  --transition EFFECT      Fade or wipe between slideshow images of the same size, over 8 frames
+  -h, --help               Print this help";
+
+    pub const STDIN_PATH: &str = "-";
+
+    const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(33);
+    const DEFAULT_MISSING_COLOR: Color = Color {red: 0, green: 0, blue: 0, alpha: 255};
+
+    pub struct Config {
+        pub path: String,
+        pub hex: bool,
+        pub histogram: bool,
+        pub pan: bool,
+        pub grid: bool,
+        pub recursive: bool,
+        pub list: bool,
+        pub verbose: bool,
+        pub stats: bool,
+        pub orientation: Orientation,
+        // Substituted for palette indices the color table does not cover
+        pub missing_color: Color,
+        pub flip: Option<Flip>,
+        // Clockwise quarter turns
+        pub rotation: usize,
+        // x, y, width and height in pixels
+        pub crop: Option<[usize; 4]>,
+        pub render: RenderOptions,
+        // File receiving the rendered escape sequences instead of the terminal
+        pub output: Option<String>,
+        // BMP file receiving the transformed image instead of showing it
+        pub save: Option<String>,
+        // Time each image of a slideshow is shown at least
+        pub frame_duration: Duration,
+        pub transition: Option<Transition>,
+        // Keybindings and UI colors from the config file
+        pub settings: Settings
+    }
+
+    impl Config {
+        // None if help was requested. The settings are loaded by the caller, so parsing doesn't touch the file system
+        pub fn parse(args: &[String], settings: Settings) -> std::io::Result<Option<Self>> {
+            let mut path = None;
+            let mut hex = false;
+            let mut histogram = false;
+            let mut pan = false;
+            let mut grid = false;
+            let mut recursive = false;
+            let mut list = false;
+            let mut verbose = false;
+            let mut stats = false;
+            let mut orientation = Orientation::Auto;
+            let mut missing_color = DEFAULT_MISSING_COLOR;
+            let mut flip = None;
+            let mut rotation = 0;
+            let mut crop = None;
+            let mut render = RenderOptions::default();
+            let mut output = None;
+            let mut save = None;
+            let mut color_mode = None;
+            let mut frame_duration = DEFAULT_FRAME_DURATION;
+            let mut transition = None;
+
+            let mut iter = args.iter().skip(1);
+            while let Some(arg) = iter.next() {
+                match arg.as_str() {
+                    "-h" | "--help" => return Ok(None),
+                    "--hex" => hex = true,
+                    "--histogram" => histogram = true,
+                    "--pan" => pan = true,
+                    "--grid" => grid = true,
+                    "--recursive" => recursive = true,
+                    "--list" => list = true,
+                    "--verbose" => verbose = true,
+                    "--stats" => stats = true,
+                    "--orient" => orientation = match next_value(&mut iter, arg)? {
+                        "top-down" => Orientation::TopDown,
+                        "bottom-up" => Orientation::BottomUp,
+                        "auto" => Orientation::Auto,
+                        value => return Err(Error::other(format!("Invalid orientation {value}\n{USAGE}")))
+                    },
+                    "--missing-color" => missing_color = Color::from_hex(next_value(&mut iter, arg)?)?,
+                    "--flip" => flip = match next_value(&mut iter, arg)? {
+                        "h" => Some(Flip::Horizontal),
+                        "v" => Some(Flip::Vertical),
+                        value => return Err(Error::other(format!("Invalid flip direction {value}\n{USAGE}")))
+                    },
+                    "--rotate" => rotation = match next_value(&mut iter, arg)? {
+                        "0" => 0,
+                        "90" => 1,
+                        "180" => 2,
+                        "270" => 3,
+                        value => return Err(Error::other(format!("Invalid rotation {value}\n{USAGE}")))
+                    },
+                    "--crop" => crop = Some(parse_crop(next_value(&mut iter, arg)?)?),
+                    "--render" => render.target = match next_value(&mut iter, arg)? {
+                        "terminal" => RenderTarget::Terminal,
+                        "null" => RenderTarget::Null,
+                        value => return Err(Error::other(format!("Invalid render target {value}\n{USAGE}")))
+                    },
+                    "--scale" => render.scale = match next_value(&mut iter, arg)? {
+                        "fit" => ScaleMode::Fit,
+                        "fill" => ScaleMode::Fill,
+                        "stretch" => ScaleMode::Stretch,
+                        value => return Err(Error::other(format!("Invalid scale mode {value}\n{USAGE}")))
+                    },
+                    "--output" => output = Some(next_value(&mut iter, arg)?.to_string()),
+                    "--save" => save = Some(next_value(&mut iter, arg)?.to_string()),
+                    "--cursor" => render.cursor = match next_value(&mut iter, arg)? {
+                        "below" => CursorEnd::Below,
+                        "keep" => CursorEnd::Keep,
+                        "home" => CursorEnd::Home,
+                        value => return Err(Error::other(format!("Invalid cursor position {value}\n{USAGE}")))
+                    },
+                    "--inset" => render.inset = parse_inset(next_value(&mut iter, arg)?)?,
+                    "--color" => color_mode = match next_value(&mut iter, arg)? {
+                        "truecolor" => Some(ColorMode::TrueColor),
+                        "256" => Some(ColorMode::Ansi256),
+                        "auto" => None,
+                        value => return Err(Error::other(format!("Invalid color mode {value}\n{USAGE}")))
+                    },
+                    "--fps" => frame_duration = match next_value(&mut iter, arg)?.parse::<u32>() {
+                        Ok(fps) if fps > 0 => Duration::from_secs(1) / fps,
+                        _ => return Err(Error::other(format!("--fps expects a positive integer\n{USAGE}")))
+                    },
+                    "--delay" => frame_duration = match next_value(&mut iter, arg)?.parse() {
+                        Ok(millis) => Duration::from_millis(millis),
+                        Err(_) => return Err(Error::other(format!("--delay expects milliseconds\n{USAGE}")))
+                    },
+                    "--dither" => render.dither = true,
+                    "--grayscale" => render.grayscale = true,
+                    "--checkerboard" => render.checkerboard = true,
+                    "--bg" => render.background = Some(Color::from_hex(next_value(&mut iter, arg)?)?),
+                    "--premultiplied" => render.premultiplied = true,
+                    "--brightness" => render.brightness = parse_float(next_value(&mut iter, arg)?, arg)?,
+                    "--contrast" => render.contrast = parse_float(next_value(&mut iter, arg)?, arg)?,
+                    "--gamma" => render.gamma = match parse_float(next_value(&mut iter, arg)?, arg)? {
+                        gamma if gamma > 0.0 => Some(GammaTable::new(gamma)),
+                        _ => return Err(Error::other(format!("--gamma expects a positive number\n{USAGE}")))
+                    },
+                    "--transition" => transition = match next_value(&mut iter, arg)? {
+                        "fade" => Some(Transition::Fade),
+                        "wipe" => Some(Transition::Wipe),
+                        value => return Err(Error::other(format!("Invalid transition {value}\n{USAGE}")))
+                    },
+                    "--pixelated" => render.sampling = Sampling::Nearest,
+                    "--smooth" => render.sampling = Sampling::Area,
+                    "--sixel" => render.backend = Backend::Sixel,
+                    "--kitty" => render.backend = Backend::Kitty,
+                    "--braille" => render.backend = Backend::Braille,
+                    "--ascii" => render.backend = Backend::Ascii,
+                    "--ascii-color" => {
+                        render.backend = Backend::Ascii;
+                        render.ascii_color = true;
+                    },
+                    "--ramp" => render.ramp = match next_value(&mut iter, arg)? {
+                        "" => return Err(Error::other(format!("--ramp expects at least one character\n{USAGE}"))),
+                        ramp => ramp.chars().collect()
+                    },
+                    "--threshold" => render.threshold = match next_value(&mut iter, arg)?.parse() {
+                        Ok(threshold) => threshold,
+                        Err(_) => return Err(Error::other(format!("--threshold expects a number from 0 to 255\n{USAGE}")))
+                    },
+                    "--fast" => render.stride = match next_value(&mut iter, arg)?.parse() {
+                        Ok(stride) if stride > 0 => stride,
+                        _ => return Err(Error::other(format!("--fast expects a positive integer\n{USAGE}")))
+                    },
+                    flag if flag.starts_with("--") => return Err(Error::other(format!("Unknown flag {flag}\n{USAGE}"))),
+                    _ if path.is_some() => return Err(Error::other(USAGE)),
+                    _ => path = Some(arg.clone())
+                }
+            }
+
+            render.color_mode = color_mode.unwrap_or_else(detect_color_mode);
+
+            // Without a path, a piped image is read from stdin
+            let path = match path {
+                Some(path) => path,
+                None if !stdin().is_terminal() => STDIN_PATH.to_string(),
+                None => return Err(Error::other(USAGE))
+            };
+
+            Ok(Some(Config {path, hex, histogram, pan, grid, recursive, list, verbose, stats, orientation, missing_color, flip, rotation, crop, render, output, save, frame_duration, transition, settings}))
+        }
+    }
+
+    fn parse_inset(value: &str) -> std::io::Result<Inset> {
+        let cells: Vec<usize> = match value.split(',').map(|cells| cells.trim().parse()).collect() {
+            Ok(cells) => cells,
+            Err(_) => return Err(Error::other(format!("Invalid inset {value}\n{USAGE}")))
+        };
+        match cells[..] {
+            [top, right, bottom, left] => Ok(Inset {top, right, bottom, left}),
+            _ => Err(Error::other(format!("--inset expects four values\n{USAGE}")))
+        }
+    }
+
+    fn parse_crop(value: &str) -> std::io::Result<[usize; 4]> {
+        let numbers: Vec<usize> = match value.split(',').map(|number| number.trim().parse()).collect() {
+            Ok(numbers) => numbers,
+            Err(_) => return Err(Error::other(format!("Invalid crop rectangle {value}\n{USAGE}")))
+        };
+        match numbers[..] {
+            [x, y, width, height] => Ok([x, y, width, height]),
+            _ => Err(Error::other(format!("--crop expects four values\n{USAGE}")))
+        }
+    }
+
+    fn parse_float(value: &str, flag: &str) -> std::io::Result<f64> {
+        match value.parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(number),
+            _ => Err(Error::other(format!("{flag} expects a number\n{USAGE}")))
+        }
+    }
+
+    fn next_value<'a, I: Iterator<Item = &'a String>>(iter: &mut I, flag: &str) -> std::io::Result<&'a str> {
+        match iter.next() {
+            Some(value) => Ok(value),
+            None => Err(Error::other(format!("Missing value for {flag}\n{USAGE}")))
+        }
+    }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn parse(args: &[&str]) -> std::io::Result<Option<Config>> {
+            let args: Vec<String> = ["term_viewer"].iter().chain(args).map(|arg| arg.to_string()).collect();
+            Config::parse(&args, Settings::default())
+        }
+
+        fn error(args: &[&str]) -> String {
+            match parse(args) {
+                Ok(_) => panic!("{args:?} was accepted"),
+                Err(err) => err.to_string()
+            }
+        }
+
+        #[test]
+        fn defaults_with_only_a_path() {
+            let config = parse(&["image.bmp"]).unwrap().unwrap();
+            assert_eq!(config.path, "image.bmp");
+            assert!(!config.hex && !config.grid && !config.list);
+            assert!(matches!(config.orientation, Orientation::Auto));
+            assert_eq!(config.frame_duration, DEFAULT_FRAME_DURATION);
+            assert!(matches!(config.render.backend, Backend::Blocks));
+            assert!(config.render.inset == Inset::default());
+        }
+
+        #[test]
+        fn flags_and_values_in_any_order() {
+            let config = parse(&["--fps", "10", "dir", "--ascii-color", "--inset", "1, 2,3,4", "--rotate", "270", "--color", "256", "--recursive"]).unwrap().unwrap();
+            assert_eq!(config.path, "dir");
+            assert_eq!(config.frame_duration, Duration::from_millis(100));
+            assert!(matches!(config.render.backend, Backend::Ascii));
+            assert!(config.render.ascii_color);
+            assert!(config.render.inset == Inset {top: 1, right: 2, bottom: 3, left: 4});
+            assert_eq!(config.rotation, 3);
+            assert!(matches!(config.render.color_mode, ColorMode::Ansi256));
+            assert!(config.recursive);
+
+            // The last backend flag wins
+            let config = parse(&["--sixel", "--braille", "image.png"]).unwrap().unwrap();
+            assert!(matches!(config.render.backend, Backend::Braille));
+        }
+
+        #[test]
+        fn help_stops_parsing() {
+            assert!(parse(&["--help"]).unwrap().is_none());
+            assert!(parse(&["image.bmp", "-h", "--unknown"]).unwrap().is_none());
+        }
+
+        #[test]
+        fn invalid_arguments_print_the_usage() {
+            assert_eq!(error(&["--unknown", "image.bmp"]), format!("Unknown flag --unknown\n{USAGE}"));
+            assert_eq!(error(&["image.bmp", "--fps"]), format!("Missing value for --fps\n{USAGE}"));
+            assert_eq!(error(&["--fps", "0", "image.bmp"]), format!("--fps expects a positive integer\n{USAGE}"));
+            assert_eq!(error(&["--rotate", "45", "image.bmp"]), format!("Invalid rotation 45\n{USAGE}"));
+            assert_eq!(error(&["--crop", "1,2,3", "image.bmp"]), format!("--crop expects four values\n{USAGE}"));
+            assert_eq!(error(&["--gamma", "-1", "image.bmp"]), format!("--gamma expects a positive number\n{USAGE}"));
+            assert_eq!(error(&["--contrast", "NaN", "image.bmp"]), format!("--contrast expects a number\n{USAGE}"));
+            assert_eq!(error(&["a.bmp", "b.bmp"]), USAGE);
+        }
+    }
+}
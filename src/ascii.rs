@@ -0,0 +1,37 @@
+pub mod ascii {
+    use std::io::Write;
+
+    use crate::ansi::ansi::Color;
+    use crate::render::render::{self, CellFit, RenderOptions};
+
+    // Draws the image centered in the region of term_height x term_width cells, returns the number of rows down to its bottom
+    pub fn render<W: Write>(pixels: &[Vec<Color>], term_height: usize, term_width: usize, options: &RenderOptions, writer: &mut W) -> std::io::Result<usize> {
+        let height = pixels.len();
+        let width = pixels.first().map_or(0, Vec::len);
+        if width == 0 || height == 0 || options.ramp.is_empty() {
+            return Ok(0);
+        }
+
+        let CellFit {x_step, y_step, x_start, y_start, width: columns, height: rows} = render::fit_cells(width, height, term_height, term_width, options);
+        let inset = render::center(columns, rows, term_height, term_width, &options.inset);
+        let last = options.ramp.len() - 1;
+        let mut fy = y_start;
+        for row in 0..rows {
+            render::set_row(writer, row, &inset)?;
+            let mut fx = x_start;
+            for _ in 0..columns {
+                let color = render::sample(pixels, fx, fy, x_step, y_step, options);
+                let character = options.ramp[(color.luma() as usize * last + 127) / 255];
+                if options.ascii_color {
+                    color.print_char(character, options.color_mode, writer)?;
+                } else {
+                    write!(writer, "{character}")?;
+                }
+                fx += x_step;
+            }
+            fy += y_step;
+        }
+
+        Ok(inset.top - options.inset.top + rows)
+    }
+}
@@ -1,292 +1,1079 @@
-pub mod bitmap {
-    use std::{fs::File, io::Read};
-    use std::io::{BufRead, BufReader, Write};
-    use std::io::{BufWriter, stdout};
-    use std::io::Error;
-    use std::path::Path;
-    use std::fmt;
-    use crate::ansi::ansi;
-    
-    use crate::common::common::{read_u16, read_u32, slice_to_usize_le};
-    use crate::ansi::ansi::{Erase, Color};
-
-    struct FileHeader {
-        bf_type: [u8; 2],
-        bf_size: u32,
-        bf_reserved: u32,
-        bf_off_bits: u32
-    }
-    
-    impl FileHeader {
-        fn from_reader<R: BufRead>(reader: &mut R) -> std::io::Result<Self> {
-            let mut bf_type = [0; 2];
-            reader.read_exact(&mut bf_type)?;
-            if &bf_type != b"BM" {
-                return Err(Error::other("File does not start with Bitmap magic values"));
-            }
-
-            let bf_size = read_u32(reader)?;
-            let bf_reserved = read_u32(reader)?;
-            let bf_off_bits = read_u32(reader)?;
-
-            Ok(FileHeader {
-                bf_type,
-                bf_size,
-                bf_reserved,
-                bf_off_bits
-            })
-        }
-    }
-
-    impl fmt::Display for FileHeader {
-        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "FILEHEADER:\n\ttype: {:?}\n\tfilesize: {}\n\toffset: {}", self.bf_type, self.bf_size, self.bf_off_bits)
-        }
-    }
-
-    struct InfoHeader {
-        bi_size: u32,
-        bi_width: i32,
-        bi_height: i32,
-        bi_planes: u16,
-        bi_bit_count: u16,
-        bi_compression: u32,
-        bi_size_image: u32,
-        bi_x_pels_per_meter: i32,
-        bi_y_pels_per_meter: i32,
-        bi_clr_used: u32,
-        bi_clr_important: u32
-    }
-    
-    impl InfoHeader {
-        fn from_reader<R: BufRead>(reader: &mut R) -> std::io::Result<Self> {
-            let bi_size = read_u32(reader)?;
-            let bi_width = read_u32(reader)? as i32;
-            let bi_height = read_u32(reader)? as i32;
-            let bi_planes = read_u16(reader)?;
-            let bi_bit_count = read_u16(reader)?;
-            let bi_compression = read_u32(reader)?;
-            let bi_size_image = read_u32(reader)?;
-            let bi_x_pels_per_meter = read_u32(reader)? as i32;
-            let bi_y_pels_per_meter = read_u32(reader)? as i32;
-            let bi_clr_used = read_u32(reader)?;
-            let bi_clr_important = read_u32(reader)?;
-
-            Ok(InfoHeader {
-                bi_size,
-                bi_width,
-                bi_height,
-                bi_planes,
-                bi_bit_count,
-                bi_compression,
-                bi_size_image,
-                bi_x_pels_per_meter,
-                bi_y_pels_per_meter,
-                bi_clr_used,
-                bi_clr_important
-            })
-        }
-    }
-
-    impl fmt::Display for InfoHeader {
-        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "INFOHEADER:\n\tinfoheader size: {}\n\twidth: {}\n\theight: {}\n\tdepth: {}\n\tcompression: {}\n\timagesize: {} \
-            \n\tclrused: {}\n\tclrimportant: {}", self.bi_size, self.bi_width, self.bi_height, self.bi_bit_count, self.bi_compression,
-            self.bi_size_image, self.bi_clr_used, self.bi_clr_important)
-        }
-    }
-
-    pub struct Bitmap {
-        pub width: usize,
-        pub height: usize,
-        pub pixels: Vec<Vec<Color>>
-    }
-    
-    impl Bitmap {
-        pub fn new(path: &Path) -> std::io::Result<Self> {
-            let file = File::open(path)?;
-            let mut reader = BufReader::new(file);
-
-            let file_header = FileHeader::from_reader(&mut reader)?;
-            let info_header = InfoHeader::from_reader(&mut reader)?;
-            if info_header.bi_compression != 0 {
-                return Err(Error::other("Compressed Bitmap files not supported right now"));
-            }
-            
-            let color_table = read_colortable(&mut reader, &file_header, &info_header)?;
-
-            let height = info_header.bi_height.abs() as usize;
-            let width = info_header.bi_width as usize;
-            let mut pixels = read_pixels(&mut reader, height, width, info_header.bi_bit_count, color_table)?;
-            
-            // Transform bottom-up to top-down
-            if info_header.bi_height > 0 {
-                pixels.reverse();
-            }
-
-            Ok(Bitmap {width, height, pixels})
-        }
-        
-        pub fn print(&self, term_height: usize, term_width: usize, prev: Option<Bitmap>) -> std::io::Result<()> {
-            let mut writer = get_larger_buffered_stdout(term_height, term_width);
-            if let None = prev {
-                ansi::erase(Erase::SCREEN, &mut writer)?;
-            }
-            ansi::reset_cursor(&mut writer)?;
-
-            let y_step: f64 = f64::max((self.height as f64) / (term_height as f64), 1.0);
-            let x_step: f64 = f64::max((self.width as f64) / (term_width as f64), 1.0);
-            let height = std::cmp::min(self.height, term_height);
-            let width = std::cmp::min(self.width, term_width);
-            
-            let mut fy: f64 = 0.0;
-            for _ in 0..height {
-                let y = fy.floor() as usize;
-                let mut fx: f64 = 0.0;
-                for cur_x in 0..width {
-                    let x = fx.floor() as usize;
-                    fx += x_step;
-                    
-                    match prev {
-                        Some(ref prev_bitmap) => {
-                            if self.pixels[y][x] != prev_bitmap.pixels[y][x] {
-                                ansi::set_horizontal(cur_x + 1, &mut writer)?;
-                                self.pixels[y][x].print(&mut writer)?;
-                            }
-                        },
-                        None => {
-                            self.pixels[y][x].print(&mut writer)?;
-                        }
-                    }
-                }
-                fy += y_step;
-                ansi::next_line(&mut writer)?;
-            }
-            writer.flush()?;
-
-            Ok(())
-        }
-    }
-
-    fn read_colortable<R: BufRead>(reader: &mut R, file_header: &FileHeader, info_header: &InfoHeader) -> std::io::Result<Vec<Color>> {
-        let num_colortable_entries = match info_header.bi_bit_count {
-            1 | 2 | 4 | 8 => {
-                if info_header.bi_clr_used == 0 {
-                    2u32.pow(info_header.bi_bit_count.into())
-                } else {
-                    info_header.bi_clr_used
-                }
-            },
-            16 | 24 | 32 => 0,
-            _ => return Err(Error::other("Not a valid bpp value"))
-        };
-            
-        if file_header.bf_off_bits < 54 + num_colortable_entries * 4 {
-            return Err(Error::other("Pixel offset too small"));
-        }
-
-        let mut color_table = Vec::new();
-        for _ in 0..num_colortable_entries {
-            let argb = read_u32(reader)?;
-            color_table.push(Color::from(argb));
-        }
-
-        // Discard remaining bytes until start of pixel data
-        let bytes_till_offset: usize = (file_header.bf_off_bits - 54 - num_colortable_entries * 4) as usize;
-        reader.consume(bytes_till_offset);
-
-        Ok(color_table)
-    }
-
-    fn read_pixels<R: BufRead>(reader: &mut R, height: usize, width: usize, bits_per_pixel: u16, color_table: Vec<Color>) -> std::io::Result<Vec<Vec<Color>>> {
-        let mut pixels = Vec::new();
-        let (bytes_per_line, reads_per_line) = match bits_per_pixel {
-            x @ (1 | 2 | 4 | 8) => (width, ((x as usize) * width)/8),
-            x @ (16 | 24 | 32) => (((x as usize) * width)/8, width),
-            _ => panic!("Not implemented yet")
-        };
-        let num_align_bytes = if bytes_per_line % 4 == 0 { 0 } else { 4 - (bytes_per_line % 4) };
-
-        for _ in 0..height {
-            let mut line = Vec::new();
-            for _ in 0..reads_per_line {
-                let res = match bits_per_pixel {
-                    x @ (1 | 2 | 4 | 8) => read_indexed(reader, &color_table, x),
-                    16 => read_16bpp(reader),
-                    24 => read_24bpp(reader),
-                    32 => read_32bpp(reader),
-                    _ => panic!("Not a valid bpp value")
-                };
-
-                if let Err(err) = res {
-                    println!("Could not read pixel values: {err}");
-                    return Err(err);
-                }
-                
-                line.append(&mut res.unwrap());
-            }
-            pixels.push(line);
-            reader.consume(num_align_bytes);
-        }
-
-        Ok(pixels)
-    }
-    
-    fn read_indexed<R: BufRead>(reader: &mut R, color_table: &Vec<Color>, bits_per_pixel: u16) -> std::io::Result<Vec<Color>> {
-        let mut buf: [u8; 1] = [0; 1];
-        reader.read_exact(&mut buf)?;
-        let mut pixels = Vec::new();
-        let start_shift = 8 - bits_per_pixel;
-        let byte = buf[0] as usize;
-        for i in 0..(8/bits_per_pixel) {
-            let index: usize = (byte >> (start_shift - bits_per_pixel*i)) & (2usize.pow(bits_per_pixel as u32) - 1);
-            if index > color_table.len() {
-                return Err(Error::other("Out-of-bounds index"));
-            }
-
-            pixels.push(color_table[index]);
-        }
-
-        Ok(pixels)
-    }
-    
-    fn read_16bpp<R: BufRead>(reader: &mut R) -> std::io::Result<Vec<Color>> {
-        let rgb = read_u16(reader)?;
-        // RGB each take 5 bit, MSB is ignored
-        let mut red = ((rgb >> 10) & 0x1F) as u8;
-        let mut green = ((rgb >> 5) & 0x1F) as u8;
-        let mut blue = (rgb & 0x1F) as u8;
-        
-        // Sign extend RGB to 8bit
-        let sign_extend = |color: &mut u8| {
-            let sign = (*color >> 4) & 1;
-            *color = (*color << 3) | 0b111*sign;
-        };
-        sign_extend(&mut red);
-        sign_extend(&mut green);
-        sign_extend(&mut blue);
-        
-        Ok(vec!(Color {red, green, blue}))
-    }
-    
-    fn read_24bpp<R: BufRead>(reader: &mut R) -> std::io::Result<Vec<Color>> {
-        let mut rgb: [u8; 3] = [0; 3];
-        reader.read_exact(&mut rgb)?;
-        let argb = slice_to_usize_le(&mut rgb) as u32;
-        Ok(vec![Color::from(argb)])
-    }
-
-    fn read_32bpp<R: BufRead>(reader: &mut R) -> std::io::Result<Vec<Color>> {
-        let argb = read_u32(reader)?;
-        Ok(vec![Color::from(argb)])
-    }
-    
-    const PAGE_SIZE: usize = 4096;
-    fn get_larger_buffered_stdout(term_height: usize, term_width: usize) -> impl Write {
-        // escape sequence for each pixel takes a few bytes, lets approximate by 16
-        let size = term_height * term_width * 16;
-        let aligned_size = if size % PAGE_SIZE == 0 { size } else { ((size / PAGE_SIZE) + 1) * PAGE_SIZE };
-        
-        BufWriter::with_capacity(aligned_size, stdout().lock())
-    }
-}
\ No newline at end of file
+pub mod bitmap {
+    use std::io::{BufRead, Write};
+    use std::io::{BufWriter, sink, stdout};
+    use std::io::Error;
+    use std::fs::File;
+    use std::path::Path;
+    use std::fmt;
+    use crate::ansi::ansi;
+    
+    use crate::common::common::{num_pixels, read_u16, read_u32, skip_bytes, slice_to_usize_le};
+    use crate::ansi::ansi::{Color, ColorMode};
+    use crate::error::error::{self, ViewerError};
+    use crate::render::render::{self, Backend, CellFit, RenderOptions, RenderTarget};
+    use crate::kitty::kitty;
+    use crate::log::log;
+    use crate::sixel::sixel;
+    use crate::braille::braille;
+    use crate::ascii::ascii;
+
+    const BI_RGB: u32 = 0;
+    const BI_RLE8: u32 = 1;
+    const BI_RLE4: u32 = 2;
+    const BI_BITFIELDS: u32 = 3;
+
+    // OS/2 BITMAPCOREHEADER, with 16 bit dimensions and 3 byte color table entries
+    const CORE_HEADER_SIZE: u32 = 12;
+
+    #[allow(dead_code)]
+    struct FileHeader {
+        bf_type: [u8; 2],
+        bf_size: u32,
+        bf_reserved: u32,
+        bf_off_bits: u32
+    }
+    
+    impl FileHeader {
+        fn from_reader<R: BufRead>(reader: &mut R) -> error::Result<Self> {
+            let mut bf_type = [0; 2];
+            reader.read_exact(&mut bf_type)?;
+            if &bf_type != b"BM" {
+                return Err(ViewerError::unsupported("File does not start with Bitmap magic values"));
+            }
+
+            let bf_size = read_u32(reader)?;
+            let bf_reserved = read_u32(reader)?;
+            let bf_off_bits = read_u32(reader)?;
+
+            Ok(FileHeader {
+                bf_type,
+                bf_size,
+                bf_reserved,
+                bf_off_bits
+            })
+        }
+
+        fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+            writer.write_all(&self.bf_type)?;
+            writer.write_all(&self.bf_size.to_le_bytes())?;
+            writer.write_all(&self.bf_reserved.to_le_bytes())?;
+            writer.write_all(&self.bf_off_bits.to_le_bytes())
+        }
+    }
+
+    impl fmt::Display for FileHeader {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "FILEHEADER:\n\ttype: {:?}\n\tfilesize: {}\n\toffset: {}", self.bf_type, self.bf_size, self.bf_off_bits)
+        }
+    }
+
+    #[allow(dead_code)]
+    struct InfoHeader {
+        bi_size: u32,
+        bi_width: i32,
+        bi_height: i32,
+        bi_planes: u16,
+        bi_bit_count: u16,
+        bi_compression: u32,
+        bi_size_image: u32,
+        bi_x_pels_per_meter: i32,
+        bi_y_pels_per_meter: i32,
+        bi_clr_used: u32,
+        bi_clr_important: u32,
+        // Only present in BITMAPV2INFOHEADER and later
+        bi_red_mask: u32,
+        bi_green_mask: u32,
+        bi_blue_mask: u32,
+        bi_alpha_mask: u32
+    }
+    
+    impl InfoHeader {
+        fn from_reader<R: BufRead>(reader: &mut R) -> error::Result<Self> {
+            let bi_size = read_u32(reader)?;
+            let info_header = if bi_size == CORE_HEADER_SIZE {
+                Self::from_core_reader(reader)?
+            } else {
+                Self::from_info_reader(reader, bi_size)?
+            };
+            // Checked before any size arithmetic, as a negative width would turn into a huge usize
+            if info_header.bi_width <= 0 || info_header.bi_height == 0 {
+                return Err(ViewerError::malformed(format!("Invalid bitmap size {}x{}", info_header.bi_width, info_header.bi_height)));
+            }
+            Ok(info_header)
+        }
+
+        fn from_info_reader<R: BufRead>(reader: &mut R, bi_size: u32) -> error::Result<Self> {
+            let bi_width = read_u32(reader)? as i32;
+            let bi_height = read_u32(reader)? as i32;
+            let bi_planes = read_u16(reader)?;
+            let bi_bit_count = read_u16(reader)?;
+            let bi_compression = read_u32(reader)?;
+            let bi_size_image = read_u32(reader)?;
+            let bi_x_pels_per_meter = read_u32(reader)? as i32;
+            let bi_y_pels_per_meter = read_u32(reader)? as i32;
+            let bi_clr_used = read_u32(reader)?;
+            let bi_clr_important = read_u32(reader)?;
+
+            let num_masks = match bi_size {
+                // BITMAPINFOHEADER stores the bitfield masks right after the header
+                40 if bi_compression == BI_BITFIELDS => 3,
+                40 => 0,
+                52 => 3,
+                56 | 108 | 124 => 4,
+                _ => return Err(ViewerError::unsupported(format!("Unsupported info header size {bi_size}")))
+            };
+            let mut masks = [0; 4];
+            for mask in masks.iter_mut().take(num_masks) {
+                *mask = read_u32(reader)?;
+            }
+            let [bi_red_mask, bi_green_mask, bi_blue_mask, bi_alpha_mask] = masks;
+
+            // Color space, gamma and ICC profile fields of BITMAPV4HEADER/BITMAPV5HEADER are not used
+            skip_bytes(reader, (bi_size as usize).saturating_sub(40 + num_masks * 4))?;
+
+            Ok(InfoHeader {
+                bi_size,
+                bi_width,
+                bi_height,
+                bi_planes,
+                bi_bit_count,
+                bi_compression,
+                bi_size_image,
+                bi_x_pels_per_meter,
+                bi_y_pels_per_meter,
+                bi_clr_used,
+                bi_clr_important,
+                bi_red_mask,
+                bi_green_mask,
+                bi_blue_mask,
+                bi_alpha_mask
+            })
+        }
+
+        fn from_core_reader<R: BufRead>(reader: &mut R) -> error::Result<Self> {
+            let bi_width = read_u16(reader)? as i32;
+            let bi_height = read_u16(reader)? as i32;
+            let bi_planes = read_u16(reader)?;
+            let bi_bit_count = read_u16(reader)?;
+
+            Ok(InfoHeader {
+                bi_size: CORE_HEADER_SIZE,
+                bi_width,
+                bi_height,
+                bi_planes,
+                bi_bit_count,
+                bi_compression: BI_RGB,
+                bi_size_image: 0,
+                bi_x_pels_per_meter: 0,
+                bi_y_pels_per_meter: 0,
+                bi_clr_used: 0,
+                bi_clr_important: 0,
+                bi_red_mask: 0,
+                bi_green_mask: 0,
+                bi_blue_mask: 0,
+                bi_alpha_mask: 0
+            })
+        }
+
+        // Only BITMAPINFOHEADER, the masks are not written
+        fn write<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+            writer.write_all(&self.bi_size.to_le_bytes())?;
+            writer.write_all(&self.bi_width.to_le_bytes())?;
+            writer.write_all(&self.bi_height.to_le_bytes())?;
+            writer.write_all(&self.bi_planes.to_le_bytes())?;
+            writer.write_all(&self.bi_bit_count.to_le_bytes())?;
+            writer.write_all(&self.bi_compression.to_le_bytes())?;
+            writer.write_all(&self.bi_size_image.to_le_bytes())?;
+            writer.write_all(&self.bi_x_pels_per_meter.to_le_bytes())?;
+            writer.write_all(&self.bi_y_pels_per_meter.to_le_bytes())?;
+            writer.write_all(&self.bi_clr_used.to_le_bytes())?;
+            writer.write_all(&self.bi_clr_important.to_le_bytes())
+        }
+
+        fn trailing_masks_size(&self) -> u32 {
+            if self.bi_size == 40 && self.bi_compression == BI_BITFIELDS { 12 } else { 0 }
+        }
+
+        fn colortable_entry_size(&self) -> u32 {
+            if self.bi_size == CORE_HEADER_SIZE { 3 } else { 4 }
+        }
+    }
+
+    impl fmt::Display for InfoHeader {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "INFOHEADER:\n\tinfoheader size: {}\n\twidth: {}\n\theight: {}\n\tdepth: {}\n\tcompression: {}\n\timagesize: {} \
+            \n\tclrused: {}\n\tclrimportant: {}", self.bi_size, self.bi_width, self.bi_height, self.bi_bit_count, self.bi_compression,
+            self.bi_size_image, self.bi_clr_used, self.bi_clr_important)
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub enum Orientation {
+        Auto,
+        TopDown,
+        BottomUp
+    }
+
+    #[derive(Copy, Clone)]
+    pub enum Flip {
+        Horizontal,
+        Vertical
+    }
+
+    // Slideshow effects between two images of the same size
+    #[derive(Copy, Clone)]
+    pub enum Transition {
+        Fade,
+        // The next image is revealed from left to right
+        Wipe
+    }
+
+    #[derive(Clone)]
+    pub struct Bitmap {
+        pub width: usize,
+        pub height: usize,
+        pub pixels: Vec<Vec<Color>>
+    }
+    
+    impl Bitmap {
+        pub fn from_reader<R: BufRead>(reader: &mut R, orientation: Orientation, missing_color: Color) -> error::Result<Self> {
+            let file_header = FileHeader::from_reader(reader)?;
+            let info_header = InfoHeader::from_reader(reader)?;
+            log::verbose(&file_header);
+            log::verbose(&info_header);
+            match (info_header.bi_compression, info_header.bi_bit_count) {
+                (BI_RGB, _) | (BI_RLE8, 8) | (BI_RLE4, 4) => {},
+                (BI_BITFIELDS, 16 | 32) => {},
+                (BI_RLE8 | BI_RLE4, _) => return Err(ViewerError::malformed("RLE compression does not match the bpp value")),
+                (BI_BITFIELDS, _) => return Err(ViewerError::unsupported("Bitfields are only supported for 16 and 32 bpp")),
+                _ => return Err(ViewerError::unsupported("Compression method not supported right now"))
+            }
+            
+            let num_colortable_entries = num_colortable_entries(&info_header)?;
+            let headers_size = 14 + info_header.bi_size + info_header.trailing_masks_size() + num_colortable_entries * info_header.colortable_entry_size();
+            if file_header.bf_off_bits < headers_size {
+                return Err(ViewerError::malformed("Pixel offset too small"));
+            }
+            let color_table = read_colortable(reader, &info_header, num_colortable_entries, missing_color)?;
+
+            // Discard remaining bytes until start of pixel data
+            let bytes_till_offset: usize = (file_header.bf_off_bits - headers_size) as usize;
+            skip_bytes(reader, bytes_till_offset)?;
+
+            let height = info_header.bi_height.unsigned_abs() as usize;
+            let width = info_header.bi_width as usize;
+            let pixels = read_pixels(reader, height, width, &info_header, color_table)?;
+            let mut bitmap = Bitmap {width, height, pixels};
+            
+            // Transform bottom-up to top-down, some exporters get the sign of the height wrong
+            let bottom_up = match orientation {
+                Orientation::Auto => info_header.bi_height > 0,
+                Orientation::TopDown => false,
+                Orientation::BottomUp => true
+            };
+            if bottom_up {
+                bitmap.flip_vertical();
+            }
+
+            Ok(bitmap)
+        }
+
+        // Icons and cursors store a DIB without file header, whose height covers both the
+        // bottom-up color image and the 1bpp AND mask following it
+        pub fn from_icon_dib<R: BufRead>(reader: &mut R, missing_color: Color) -> error::Result<Self> {
+            let mut info_header = InfoHeader::from_reader(reader)?;
+            log::verbose(&info_header);
+            if info_header.bi_compression != 0 {
+                return Err(ViewerError::unsupported("Compressed icon images not supported"));
+            }
+
+            let num_colortable_entries = num_colortable_entries(&info_header)?;
+            let color_table = read_colortable(reader, &info_header, num_colortable_entries, missing_color)?;
+            // Entries beyond the clamped count precede the pixels directly, as there is no offset to skip to
+            let extra_entries = info_header.bi_clr_used.saturating_sub(num_colortable_entries);
+            skip_bytes(reader, extra_entries as usize * info_header.colortable_entry_size() as usize)?;
+
+            let height = (info_header.bi_height.unsigned_abs() / 2) as usize;
+            let width = info_header.bi_width as usize;
+            if height == 0 {
+                return Err(ViewerError::malformed("Icon image without rows"));
+            }
+            // 32 bpp icons keep their alpha in the top byte, older ones leave it 0 and only have the AND mask
+            if info_header.bi_bit_count == 32 {
+                info_header.bi_alpha_mask = 0xff000000;
+            }
+            let mut pixels = read_pixels(reader, height, width, &info_header, color_table)?;
+            let has_alpha = info_header.bi_bit_count == 32 && pixels.iter().flatten().any(|pixel| pixel.alpha != 0);
+            if !has_alpha {
+                apply_and_mask(reader, &mut pixels, width)?;
+            }
+            pixels.reverse();
+
+            Ok(Bitmap {width, height, pixels})
+        }
+        
+        // Uncompressed 24 bpp with bottom-up rows, alpha is dropped
+        pub fn write_bmp(&self, path: &Path) -> std::io::Result<()> {
+            let bytes_per_line = self.width * 3;
+            let num_align_bytes = (4 - bytes_per_line % 4) % 4;
+            let image_size = (bytes_per_line + num_align_bytes) * self.height;
+            let (Ok(width), Ok(height), Ok(image_size)) = (i32::try_from(self.width), i32::try_from(self.height), u32::try_from(image_size)) else {
+                return Err(Error::other("Image too large to be saved as a BMP file"));
+            };
+            let headers_size = 14 + 40;
+            let file_header = FileHeader {
+                bf_type: *b"BM",
+                bf_size: image_size.saturating_add(headers_size),
+                bf_reserved: 0,
+                bf_off_bits: headers_size
+            };
+            let info_header = InfoHeader {
+                bi_size: 40,
+                bi_width: width,
+                bi_height: height,
+                bi_planes: 1,
+                bi_bit_count: 24,
+                bi_compression: BI_RGB,
+                bi_size_image: image_size,
+                bi_x_pels_per_meter: 0,
+                bi_y_pels_per_meter: 0,
+                bi_clr_used: 0,
+                bi_clr_important: 0,
+                bi_red_mask: 0,
+                bi_green_mask: 0,
+                bi_blue_mask: 0,
+                bi_alpha_mask: 0
+            };
+
+            let mut writer = BufWriter::new(File::create(path)?);
+            file_header.write(&mut writer)?;
+            info_header.write(&mut writer)?;
+            for row in self.pixels.iter().rev() {
+                for pixel in row {
+                    writer.write_all(&[pixel.blue, pixel.green, pixel.red])?;
+                }
+                writer.write_all(&[0; 3][..num_align_bytes])?;
+            }
+
+            writer.flush()
+        }
+
+        pub fn flip_horizontal(&mut self) {
+            for row in self.pixels.iter_mut() {
+                row.reverse();
+            }
+        }
+
+        pub fn flip_vertical(&mut self) {
+            self.pixels.reverse();
+        }
+
+        // Clockwise, the bottom left pixel becomes the top left one
+        pub fn rotate_90(&mut self) {
+            self.pixels = (0..self.width).map(|x| (0..self.height).rev().map(|y| self.pixels[y][x]).collect()).collect();
+            std::mem::swap(&mut self.width, &mut self.height);
+        }
+
+        // Intermediate image at progress t between 0 and 1 of a transition to the image of the same size
+        pub fn transition(&self, to: &Bitmap, transition: Transition, t: f64) -> Bitmap {
+            let wipe_x = (t * self.width as f64).round() as usize;
+            let pixels = self.pixels.iter().zip(&to.pixels).map(|(from_row, to_row)| {
+                from_row.iter().zip(to_row).enumerate().map(|(x, (&from, &to))| match transition {
+                    Transition::Fade => Color::blend(from, to, t),
+                    Transition::Wipe => if x < wipe_x { to } else { from }
+                }).collect()
+            }).collect();
+
+            Bitmap {width: self.width, height: self.height, pixels}
+        }
+
+        // Sub-rectangle clamped to the image bounds
+        pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> std::io::Result<Bitmap> {
+            if x >= self.width || y >= self.height || width == 0 || height == 0 {
+                return Err(Error::other(format!("Crop rectangle lies outside of the {}x{} image", self.width, self.height)));
+            }
+
+            let width = std::cmp::min(width, self.width - x);
+            let height = std::cmp::min(height, self.height - y);
+            let pixels = self.pixels[y..y + height].iter().map(|row| row[x..x + width].to_vec()).collect();
+            Ok(Bitmap {width, height, pixels})
+        }
+
+        // Average of the most populated bucket when quantizing to 5 bits per channel
+        pub fn dominant_color(&self) -> Color {
+            let bucket = |color: &Color| ((color.red as usize >> 3) << 10) | ((color.green as usize >> 3) << 5) | (color.blue as usize >> 3);
+            let mut counts = vec![0u32; 1 << 15];
+            for pixel in self.pixels.iter().flatten() {
+                counts[bucket(pixel)] += 1;
+            }
+            let Some(dominant) = (0..counts.len()).max_by_key(|&i| counts[i]) else {
+                return Color {red: 0, green: 0, blue: 0, alpha: 255};
+            };
+
+            let (mut red, mut green, mut blue, mut count) = (0u64, 0u64, 0u64, 0u64);
+            for pixel in self.pixels.iter().flatten().filter(|pixel| bucket(pixel) == dominant) {
+                red += pixel.red as u64;
+                green += pixel.green as u64;
+                blue += pixel.blue as u64;
+                count += 1;
+            }
+            if count == 0 {
+                return Color {red: 0, green: 0, blue: 0, alpha: 255};
+            }
+
+            Color {red: (red / count) as u8, green: (green / count) as u8, blue: (blue / count) as u8, alpha: 255}
+        }
+
+        // Returns the number of bytes written to the terminal
+        pub fn print(&self, term_height: usize, term_width: usize, prev: Option<Bitmap>, options: &RenderOptions) -> std::io::Result<usize> {
+            match options.target {
+                RenderTarget::Terminal => {
+                    let mut writer = get_larger_buffered_stdout(term_height, term_width);
+                    self.render_with_hidden_cursor(term_height, term_width, prev, options, &mut writer)?;
+                    Ok(writer.count)
+                },
+                RenderTarget::Null => {
+                    let mut writer = CountingWriter {inner: sink(), count: 0};
+                    self.render_to(term_height, term_width, prev, options, &mut writer)?;
+                    Ok(writer.count)
+                }
+            }
+        }
+
+        // Writes what print would send to the terminal into a file, to be shown later with e.g. cat.
+        // The image is rendered first, so a failed render leaves no partial file behind
+        pub fn print_to_file(&self, term_height: usize, term_width: usize, options: &RenderOptions, path: &Path) -> std::io::Result<usize> {
+            let rendered = self.render_to_string(term_height, term_width, options)?;
+            let mut writer = CountingWriter {inner: BufWriter::new(File::create(path)?), count: 0};
+            ansi::hide_cursor(&mut writer)?;
+            writer.write_all(rendered.as_bytes())?;
+            ansi::show_cursor(&mut writer)?;
+            writer.flush()?;
+            Ok(writer.count)
+        }
+
+        fn render_with_hidden_cursor<W: Write>(&self, term_height: usize, term_width: usize, prev: Option<Bitmap>, options: &RenderOptions, writer: &mut W) -> std::io::Result<()> {
+            // Keeps the cursor from flickering across the image while it is painted
+            ansi::hide_cursor(writer)?;
+            let result = self.render_to(term_height, term_width, prev, options, writer);
+            // Shown again even if rendering failed halfway
+            let shown = ansi::show_cursor(writer).and_then(|_| writer.flush());
+            result.and(shown)
+        }
+
+        // Escape sequences of the image as print would write them to the terminal, for embedding the renderer elsewhere
+        pub fn render_to_string(&self, term_height: usize, term_width: usize, options: &RenderOptions) -> std::io::Result<String> {
+            let mut buffer = Vec::new();
+            self.render_to(term_height, term_width, None, options, &mut buffer)?;
+            String::from_utf8(buffer).map_err(Error::other)
+        }
+
+        // Draws into term_height x term_width cells of any writer, prev is diffed against like in print
+        pub fn render_to<W: Write>(&self, term_height: usize, term_width: usize, prev: Option<Bitmap>, options: &RenderOptions, writer: &mut W) -> std::io::Result<()> {
+            // Diffing only works against a previous image of the same size
+            let prev = prev.filter(|prev_bitmap| prev_bitmap.width == self.width && prev_bitmap.height == self.height);
+            if prev.is_none() {
+                render::clear_region(writer, term_height, term_width, &options.inset)?;
+            }
+            // Pixel graphics are always drawn in full, they replace the previous image of the same size
+            let rows_rendered = match options.backend {
+                Backend::Blocks => self.render_blocks(term_height, term_width, prev.as_ref(), options, writer)?,
+                Backend::Sixel => sixel::render(&self.pixels, term_height, term_width, options, writer)?,
+                Backend::Kitty => kitty::render(&self.pixels, term_height, term_width, options, writer)?,
+                Backend::Braille => braille::render(&self.pixels, term_height, term_width, options, writer)?,
+                Backend::Ascii => ascii::render(&self.pixels, term_height, term_width, options, writer)?
+            };
+            render::finish(writer, rows_rendered, options)?;
+            writer.flush()
+        }
+
+        // Draws the cells that differ from prev, or all of them without prev, centered in the region of term_height x term_width
+        // cells at the inset. Neither clears the screen nor moves the cursor afterwards, returns the number of rows down to the
+        // bottom of the image
+        pub fn render_blocks<W: Write>(&self, term_height: usize, term_width: usize, prev: Option<&Bitmap>, options: &RenderOptions, writer: &mut W) -> std::io::Result<usize> {
+            let CellFit {x_step, y_step, x_start, y_start, width, height} = render::fit_cells(self.width, self.height, term_height, term_width, options);
+            let inset = render::center(width, height, term_height, term_width, &options.inset);
+            
+            let dither = options.dither && options.color_mode == ColorMode::Ansi256;
+            let mut fy: f64 = y_start;
+            for row in 0..height {
+                render::set_row(writer, row, &inset)?;
+                // Unchanged cells are skipped with a single cursor movement per run
+                let mut skipped = 0;
+                let mut fx: f64 = x_start;
+                for cur_x in 0..width {
+                    let color = render::sample(&self.pixels, fx, fy, x_step, y_step, options);
+                    let shown = if options.checkerboard && color.alpha == 0 {
+                        render::checkerboard(cur_x, row)
+                    } else if dither {
+                        render::dither(color, cur_x, row)
+                    } else {
+                        color
+                    };
+
+                    match prev {
+                        Some(prev_bitmap) => {
+                            let prev_color = render::sample(&prev_bitmap.pixels, fx, fy, x_step, y_step, options);
+                            if color == prev_color {
+                                skipped += 1;
+                            } else {
+                                if skipped > 0 {
+                                    ansi::cursor_forward(skipped, writer)?;
+                                    skipped = 0;
+                                }
+                                shown.print(options.color_mode, writer)?;
+                            }
+                        },
+                        None => {
+                            shown.print(options.color_mode, writer)?;
+                        }
+                    }
+                    fx += x_step;
+                }
+                fy += y_step;
+            }
+
+            Ok(inset.top - options.inset.top + height)
+        }
+    }
+
+    // bi_clr_used may declare a partial palette, a count above what the bpp can index is clamped
+    fn num_colortable_entries(info_header: &InfoHeader) -> error::Result<u32> {
+        match info_header.bi_bit_count {
+            1 | 2 | 4 | 8 => {
+                let max_entries = 2u32.pow(info_header.bi_bit_count.into());
+                if info_header.bi_clr_used == 0 {
+                    Ok(max_entries)
+                } else {
+                    Ok(std::cmp::min(info_header.bi_clr_used, max_entries))
+                }
+            },
+            16 | 24 | 32 => Ok(0),
+            _ => Err(ViewerError::malformed("Not a valid bpp value"))
+        }
+    }
+
+    // Indices past a partial palette are shown in missing_color
+    fn read_colortable<R: BufRead>(reader: &mut R, info_header: &InfoHeader, num_colortable_entries: u32, missing_color: Color) -> error::Result<Vec<Color>> {
+        let mut color_table = Vec::new();
+        let mut entry = [0; 4];
+        let entry_size = info_header.colortable_entry_size() as usize;
+        for _ in 0..num_colortable_entries {
+            reader.read_exact(&mut entry[..entry_size])?;
+            let argb = slice_to_usize_le(&entry[..entry_size]) as u32;
+            color_table.push(Color::from(argb));
+        }
+        if num_colortable_entries > 0 {
+            color_table.resize(1 << info_header.bi_bit_count, missing_color);
+        }
+
+        Ok(color_table)
+    }
+
+    fn read_pixels<R: BufRead>(reader: &mut R, height: usize, width: usize, info_header: &InfoHeader, color_table: Vec<Color>) -> error::Result<Vec<Vec<Color>>> {
+        let bits_per_pixel = info_header.bi_bit_count;
+        if num_pixels(width, height).is_none() {
+            return Err(ViewerError::malformed("Bitmap too large"));
+        }
+        if info_header.bi_compression == BI_RLE8 || info_header.bi_compression == BI_RLE4 {
+            return read_rle(reader, height, width, bits_per_pixel, &color_table);
+        }
+
+        let mut pixels = Vec::new();
+        // Indexed pixels are packed into bytes, the last byte of a row may hold unused bits
+        let Some(bits_per_line) = (bits_per_pixel as usize).checked_mul(width) else {
+            return Err(ViewerError::malformed("Bitmap too large"));
+        };
+        let bytes_per_line = bits_per_line.div_ceil(8);
+        let reads_per_line = match bits_per_pixel {
+            1 | 2 | 4 | 8 => bytes_per_line,
+            16 | 24 | 32 => width,
+            _ => panic!("Not implemented yet")
+        };
+        // Rows are padded to a multiple of 4 bytes
+        let num_align_bytes = bits_per_line.div_ceil(32) * 4 - bytes_per_line;
+
+        for _ in 0..height {
+            let mut line = Vec::new();
+            for _ in 0..reads_per_line {
+                let res = match bits_per_pixel {
+                    x @ (1 | 2 | 4 | 8) => read_indexed(reader, &color_table, x),
+                    x @ (16 | 32) if info_header.bi_compression == BI_BITFIELDS => read_bitfields(reader, x, info_header),
+                    16 => read_16bpp(reader),
+                    24 => read_24bpp(reader),
+                    32 => read_32bpp(reader, info_header.bi_alpha_mask),
+                    _ => panic!("Not a valid bpp value")
+                };
+
+                if let Err(err) = res {
+                    log::verbose(format_args!("Could not read pixel values: {err}"));
+                    return Err(err);
+                }
+                
+                line.append(&mut res.unwrap());
+            }
+            line.truncate(width);
+            pixels.push(line);
+            skip_bytes(reader, num_align_bytes)?;
+        }
+
+        Ok(pixels)
+    }
+    
+    // Pixels whose bit is set in the 1 bpp mask are transparent, the others opaque.
+    // Mask rows are bottom-up like the pixels and padded to a multiple of 4 bytes
+    fn apply_and_mask<R: BufRead>(reader: &mut R, pixels: &mut [Vec<Color>], width: usize) -> error::Result<()> {
+        let mut line = vec![0; width.div_ceil(32) * 4];
+        for row in pixels.iter_mut() {
+            reader.read_exact(&mut line)?;
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let transparent = line[x / 8] & (0x80 >> (x % 8)) != 0;
+                pixel.alpha = if transparent { 0 } else { 255 };
+            }
+        }
+        Ok(())
+    }
+
+    // RLE8/RLE4: pairs of (count, index) runs, or a zero count followed by an escape. Escapes are
+    // end of line, end of bitmap, a delta jump, or an absolute run padded to a word boundary.
+    // Pixels skipped by end of line/delta keep color index 0. As a short stream may cover the whole image,
+    // rows are only allocated once the stream reaches them, so a truncated stream fails before the full allocation
+    fn read_rle<R: BufRead>(reader: &mut R, height: usize, width: usize, bits_per_pixel: u16, color_table: &[Color]) -> error::Result<Vec<Vec<Color>>> {
+        let mut indices: Vec<Vec<u8>> = Vec::new();
+        let (mut x, mut y) = (0, 0);
+        let mut put = |x: &mut usize, y: usize, index: u8| {
+            // Runs overflowing a line are clipped
+            if *x < width {
+                if indices.len() <= y {
+                    indices.resize(y + 1, vec![0; width]);
+                }
+                indices[y][*x] = index;
+            }
+            *x += 1;
+        };
+
+        while y < height {
+            let mut pair = [0; 2];
+            reader.read_exact(&mut pair)?;
+            let [count, value] = pair;
+
+            if count > 0 {
+                for i in 0..count {
+                    let index = match bits_per_pixel {
+                        4 if i % 2 == 0 => value >> 4,
+                        4 => value & 0xf,
+                        _ => value
+                    };
+                    put(&mut x, y, index);
+                }
+                continue;
+            }
+
+            match value {
+                0 => {
+                    x = 0;
+                    y += 1;
+                },
+                1 => break,
+                2 => {
+                    let mut delta = [0; 2];
+                    reader.read_exact(&mut delta)?;
+                    x += delta[0] as usize;
+                    y += delta[1] as usize;
+                    if x > width || y > height {
+                        return Err(ViewerError::malformed("RLE delta jumps past the image bounds"));
+                    }
+                },
+                num_pixels => {
+                    let num_bytes = if bits_per_pixel == 4 { (num_pixels as usize).div_ceil(2) } else { num_pixels as usize };
+                    let mut bytes = vec![0; num_bytes + num_bytes % 2];
+                    reader.read_exact(&mut bytes)?;
+                    for i in 0..num_pixels as usize {
+                        let index = match bits_per_pixel {
+                            4 if i % 2 == 0 => bytes[i / 2] >> 4,
+                            4 => bytes[i / 2] & 0xf,
+                            _ => bytes[i]
+                        };
+                        put(&mut x, y, index);
+                    }
+                }
+            }
+        }
+
+        indices.resize(height, vec![0; width]);
+        let mut pixels = Vec::with_capacity(height);
+        for row in indices {
+            let mut line = Vec::with_capacity(width);
+            for index in row {
+                match color_table.get(index as usize) {
+                    Some(&color) => line.push(color),
+                    None => return Err(ViewerError::malformed("Out-of-bounds index"))
+                }
+            }
+            pixels.push(line);
+        }
+
+        Ok(pixels)
+    }
+
+    fn read_indexed<R: BufRead>(reader: &mut R, color_table: &[Color], bits_per_pixel: u16) -> error::Result<Vec<Color>> {
+        let mut buf: [u8; 1] = [0; 1];
+        reader.read_exact(&mut buf)?;
+        let mut pixels = Vec::new();
+        let start_shift = 8 - bits_per_pixel;
+        let byte = buf[0] as usize;
+        for i in 0..(8/bits_per_pixel) {
+            let index: usize = (byte >> (start_shift - bits_per_pixel*i)) & (2usize.pow(bits_per_pixel as u32) - 1);
+            if index >= color_table.len() {
+                return Err(ViewerError::malformed("Out-of-bounds index"));
+            }
+
+            pixels.push(color_table[index]);
+        }
+
+        Ok(pixels)
+    }
+    
+    fn read_16bpp<R: BufRead>(reader: &mut R) -> error::Result<Vec<Color>> {
+        let rgb = read_u16(reader)?;
+        // RGB each take 5 bit, MSB is ignored
+        let red = ((rgb >> 10) & 0x1F) as u8;
+        let green = ((rgb >> 5) & 0x1F) as u8;
+        let blue = (rgb & 0x1F) as u8;
+        
+        // Replicate the high bits into the low bits, so 0 maps to 0 and 31 to 255
+        let expand = |color: u8| (color << 3) | (color >> 2);
+        
+        Ok(vec!(Color {red: expand(red), green: expand(green), blue: expand(blue), alpha: 255}))
+    }
+    
+    fn read_24bpp<R: BufRead>(reader: &mut R) -> error::Result<Vec<Color>> {
+        let mut rgb: [u8; 3] = [0; 3];
+        reader.read_exact(&mut rgb)?;
+        let argb = slice_to_usize_le(&rgb) as u32;
+        Ok(vec![Color::from(argb)])
+    }
+
+    fn read_32bpp<R: BufRead>(reader: &mut R, alpha_mask: u32) -> error::Result<Vec<Color>> {
+        let argb = read_u32(reader)?;
+        let mut color = Color::from(argb);
+        if alpha_mask != 0 {
+            color.alpha = extract_channel(argb, alpha_mask);
+        }
+        Ok(vec![color])
+    }
+
+    fn read_bitfields<R: BufRead>(reader: &mut R, bits_per_pixel: u16, info_header: &InfoHeader) -> error::Result<Vec<Color>> {
+        let value = if bits_per_pixel == 16 { read_u16(reader)? as u32 } else { read_u32(reader)? };
+        let channel = |mask: u32, missing: u8| if mask == 0 { missing } else { extract_channel(value, mask) };
+        Ok(vec![Color {
+            red: channel(info_header.bi_red_mask, 0),
+            green: channel(info_header.bi_green_mask, 0),
+            blue: channel(info_header.bi_blue_mask, 0),
+            alpha: channel(info_header.bi_alpha_mask, 255)
+        }])
+    }
+
+    // Scales the bits selected by a contiguous mask to 8 bits
+    fn extract_channel(value: u32, mask: u32) -> u8 {
+        let shift = mask.trailing_zeros();
+        let bits = (mask >> shift).count_ones();
+        let channel = (value & mask) >> shift;
+        if bits >= 8 {
+            return (channel >> (bits - 8)) as u8;
+        }
+
+        let max = (1 << bits) - 1;
+        ((channel * 255 + max / 2) / max) as u8
+    }
+    
+    // Counts the bytes passing through, for --stats
+    struct CountingWriter<W: Write> {
+        inner: W,
+        count: usize
+    }
+
+    impl<W: Write> Write for CountingWriter<W> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let written = self.inner.write(buf)?;
+            self.count += written;
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    const PAGE_SIZE: usize = 4096;
+    fn get_larger_buffered_stdout(term_height: usize, term_width: usize) -> CountingWriter<impl Write> {
+        // escape sequence for each pixel takes a few bytes, lets approximate by 16
+        let size = term_height * term_width * 16;
+        let aligned_size = if size.is_multiple_of(PAGE_SIZE) { size } else { ((size / PAGE_SIZE) + 1) * PAGE_SIZE };
+        
+        CountingWriter {inner: BufWriter::with_capacity(aligned_size, stdout().lock()), count: 0}
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const BLACK: Color = Color {red: 0, green: 0, blue: 0, alpha: 255};
+        const WHITE: Color = Color {red: 255, green: 255, blue: 255, alpha: 255};
+
+        // BITMAPINFOHEADER file with the masks or palette between header and pixel data, rows are given padded
+        fn bmp(width: i32, height: i32, bpp: u16, compression: u32, extra: &[u32], data: &[u8]) -> Vec<u8> {
+            let clr_used = if bpp <= 8 { extra.len() as u32 } else { 0 };
+            let offset = 14 + 40 + extra.len() as u32 * 4;
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(b"BM");
+            for value in [offset + data.len() as u32, 0, offset, 40] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&width.to_le_bytes());
+            bytes.extend_from_slice(&height.to_le_bytes());
+            bytes.extend_from_slice(&1u16.to_le_bytes());
+            bytes.extend_from_slice(&bpp.to_le_bytes());
+            for value in [compression, data.len() as u32, 0, 0, clr_used, 0] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in extra {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(data);
+            bytes
+        }
+
+        fn decode(bytes: &[u8]) -> error::Result<Bitmap> {
+            Bitmap::from_reader(&mut &bytes[..], Orientation::TopDown, BLACK)
+        }
+
+        #[test]
+        fn indexed_rows_narrower_than_a_byte() {
+            // 4 bpp, 3 pixels take 2 bytes padded to 4
+            let bitmap = decode(&bmp(3, 2, 4, BI_RGB, &[0x000000, 0xffffff], &[0x01, 0x00, 0, 0, 0x10, 0x10, 0, 0])).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![BLACK, WHITE, BLACK], vec![WHITE, BLACK, WHITE]]);
+
+            let bitmap = decode(&bmp(3, 1, 1, BI_RGB, &[0x000000, 0xffffff], &[0b1010_0000, 0, 0, 0])).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![WHITE, BLACK, WHITE]]);
+        }
+
+        #[test]
+        fn indexed_rows_padded_from_packed_size() {
+            // 16 pixels at 1 bpp take 2 bytes, padded to 4 rather than to the width
+            let data = [0xff, 0x00, 0, 0, 0x00, 0xff, 0, 0];
+            let bitmap = decode(&bmp(16, 2, 1, BI_RGB, &[0x000000, 0xffffff], &data)).unwrap();
+            assert_eq!(bitmap.pixels[0], [vec![WHITE; 8], vec![BLACK; 8]].concat());
+            assert_eq!(bitmap.pixels[1], [vec![BLACK; 8], vec![WHITE; 8]].concat());
+        }
+
+        #[test]
+        fn rgb555_channels_expand_to_full_range() {
+            let red = read_16bpp(&mut &0x7c00u16.to_le_bytes()[..]).unwrap();
+            assert_eq!(red, vec![Color {red: 255, green: 0, blue: 0, alpha: 255}]);
+
+            // 15 of 31 on every channel
+            let gray = read_16bpp(&mut &0x3defu16.to_le_bytes()[..]).unwrap();
+            assert_eq!(gray, vec![Color {red: 123, green: 123, blue: 123, alpha: 255}]);
+        }
+
+        #[test]
+        fn bitfields_565() {
+            let mut data = Vec::new();
+            for value in [0xf800u16, 0x07e0, 0x001f, 0x8410] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            let bitmap = decode(&bmp(4, 1, 16, BI_BITFIELDS, &[0xf800, 0x07e0, 0x001f], &data)).unwrap();
+            assert_eq!(bitmap.pixels[0], vec![
+                Color {red: 255, green: 0, blue: 0, alpha: 255},
+                Color {red: 0, green: 255, blue: 0, alpha: 255},
+                Color {red: 0, green: 0, blue: 255, alpha: 255},
+                Color {red: 132, green: 130, blue: 132, alpha: 255}
+            ]);
+        }
+
+        #[test]
+        fn bitfields_32bpp_with_alpha_mask() {
+            // BITMAPV3INFOHEADER, the alpha mask follows the color masks inside the header
+            let mut bytes = bmp(1, 1, 32, BI_BITFIELDS, &[0x00ff0000, 0x0000ff00, 0x000000ff, 0xff000000], &0x80112233u32.to_le_bytes());
+            bytes[14..18].copy_from_slice(&56u32.to_le_bytes());
+            let bitmap = decode(&bytes).unwrap();
+            assert_eq!(bitmap.pixels[0], vec![Color {red: 0x11, green: 0x22, blue: 0x33, alpha: 0x80}]);
+        }
+
+        #[test]
+        fn render_to_string_2x2() {
+            let red = Color {red: 255, green: 0, blue: 0, alpha: 255};
+            let bitmap = Bitmap {width: 2, height: 2, pixels: vec![vec![red, WHITE], vec![BLACK, red]]};
+            // Stretch maps each pixel to one cell, independent of the cell size the terminal reports
+            let options = RenderOptions {scale: render::ScaleMode::Stretch, ..RenderOptions::default()};
+            let rendered = bitmap.render_to_string(2, 2, &options).unwrap();
+            assert_eq!(rendered, "\x1b[2J\
+                \x1b[1;1H\x1b[38;2;255;0;0m█\x1b[m\x1b[38;2;255;255;255m█\x1b[m\
+                \x1b[2;1H\x1b[38;2;0;0;0m█\x1b[m\x1b[38;2;255;0;0m█\x1b[m\
+                \x1b[3;1H");
+        }
+
+        #[test]
+        fn unchanged_run_is_skipped_with_one_cursor_movement() {
+            let red = Color {red: 255, green: 0, blue: 0, alpha: 255};
+            let prev = Bitmap {width: 12, height: 1, pixels: vec![vec![BLACK; 12]]};
+            let mut pixels = vec![BLACK; 12];
+            pixels[0] = red;
+            pixels[11] = red;
+            let bitmap = Bitmap {width: 12, height: 1, pixels: vec![pixels]};
+            let options = RenderOptions {scale: render::ScaleMode::Stretch, ..RenderOptions::default()};
+
+            let mut output = Vec::new();
+            bitmap.render_blocks(1, 12, Some(&prev), &options, &mut output).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), "\x1b[1;1H\x1b[38;2;255;0;0m█\x1b[m\x1b[10C\x1b[38;2;255;0;0m█\x1b[m");
+        }
+
+        // 3 wide and 2 high, every pixel distinct
+        fn asymmetric() -> Bitmap {
+            let pixels = (0..2).map(|y| (0..3).map(|x| Color {red: x, green: y, blue: 0, alpha: 255}).collect()).collect();
+            Bitmap {width: 3, height: 2, pixels}
+        }
+
+        fn positions(bitmap: &Bitmap) -> Vec<Vec<(u8, u8)>> {
+            bitmap.pixels.iter().map(|row| row.iter().map(|color| (color.red, color.green)).collect()).collect()
+        }
+
+        #[test]
+        fn flips_mirror_an_asymmetric_grid() {
+            let mut bitmap = asymmetric();
+            bitmap.flip_horizontal();
+            assert_eq!(positions(&bitmap), vec![vec![(2, 0), (1, 0), (0, 0)], vec![(2, 1), (1, 1), (0, 1)]]);
+
+            let mut bitmap = asymmetric();
+            bitmap.flip_vertical();
+            assert_eq!(positions(&bitmap), vec![vec![(0, 1), (1, 1), (2, 1)], vec![(0, 0), (1, 0), (2, 0)]]);
+        }
+
+        #[test]
+        fn rotate_90_turns_clockwise() {
+            let mut bitmap = asymmetric();
+            bitmap.rotate_90();
+            assert_eq!((bitmap.width, bitmap.height), (2, 3));
+            assert_eq!(positions(&bitmap), vec![vec![(0, 1), (0, 0)], vec![(1, 1), (1, 0)], vec![(2, 1), (2, 0)]]);
+
+            // Four quarter turns give back the original
+            for _ in 0..3 {
+                bitmap.rotate_90();
+            }
+            assert_eq!(positions(&bitmap), positions(&asymmetric()));
+        }
+
+        #[test]
+        fn rle8_runs_and_escapes() {
+            // Run of 3, end of line, absolute run of 3 padded to a word, end of bitmap
+            let data = [3, 1, 0, 0, 0, 3, 1, 0, 1, 0, 0, 1];
+            let bitmap = decode(&bmp(4, 2, 8, BI_RLE8, &[0x000000, 0xffffff], &data)).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![WHITE, WHITE, WHITE, BLACK], vec![WHITE, BLACK, WHITE, BLACK]]);
+        }
+
+        #[test]
+        fn oversized_rle_header_is_an_error() {
+            let bytes = bmp(0x7fff, 0x7fff0000, 8, BI_RLE8, &[0x000000, 0xffffff], &[0, 1]);
+            assert!(matches!(decode(&bytes), Err(ViewerError::Malformed {..})));
+        }
+
+        #[test]
+        fn negative_width_and_empty_height_are_errors() {
+            // Header only, 54 bytes
+            let bytes = bmp(-1, 0, 24, BI_RGB, &[], &[]);
+            assert_eq!(bytes.len(), 54);
+            assert!(matches!(decode(&bytes), Err(ViewerError::Malformed {..})));
+            assert!(matches!(decode(&bmp(0, 4, 24, BI_RGB, &[], &[])), Err(ViewerError::Malformed {..})));
+        }
+
+        // Palette indices of the cells of every row, from the 256 color escape sequences
+        fn ansi256_rows(rendered: &str) -> Vec<Vec<u8>> {
+            rendered.split("H").skip(1)
+                .map(|row| row.split("\x1b[38;5;").skip(1).map(|cell| cell[..cell.find('m').unwrap()].parse().unwrap()).collect())
+                .filter(|row: &Vec<u8>| !row.is_empty())
+                .collect()
+        }
+
+        // Whether every palette index of the row shows up as one contiguous band
+        fn banded(row: &[u8]) -> bool {
+            let mut seen = Vec::new();
+            for (i, &index) in row.iter().enumerate() {
+                if i > 0 && row[i - 1] != index {
+                    if seen.contains(&index) {
+                        return false;
+                    }
+                    seen.push(row[i - 1]);
+                }
+            }
+            true
+        }
+
+        #[test]
+        fn dither_breaks_up_gradient_bands() {
+            let ramp = (0..64).map(|x| Color {red: (x * 4) as u8, green: (x * 4) as u8, blue: (x * 4) as u8, alpha: 255}).collect();
+            let bitmap = Bitmap {width: 64, height: 4, pixels: vec![ramp; 4]};
+            let options = RenderOptions {scale: render::ScaleMode::Stretch, color_mode: ColorMode::Ansi256, ..RenderOptions::default()};
+
+            let plain = ansi256_rows(&bitmap.render_to_string(4, 64, &options).unwrap());
+            assert_eq!(plain.len(), 4);
+            assert!(plain.iter().all(|row| row.len() == 64 && banded(row)));
+
+            let dithered = ansi256_rows(&bitmap.render_to_string(4, 64, &RenderOptions {dither: true, ..options}).unwrap());
+            assert_eq!(dithered.len(), 4);
+            assert!(dithered.iter().all(|row| row.len() == 64 && !banded(row)));
+            // The pattern also differs between neighboring rows
+            assert_ne!(dithered[0], dithered[1]);
+        }
+
+        // Icon DIB of width x height pixels, whose header height also covers the AND mask after the pixels
+        fn icon_dib(width: i32, height: i32, bpp: u16, extra: &[u32], data: &[u8], mask: &[u8]) -> error::Result<Bitmap> {
+            let mut bytes = bmp(width, height * 2, bpp, BI_RGB, extra, data).split_off(14);
+            bytes.extend_from_slice(mask);
+            Bitmap::from_icon_dib(&mut &bytes[..], BLACK)
+        }
+
+        #[test]
+        fn icon_and_mask_makes_pixels_transparent() {
+            let clear = |color: Color| Color {alpha: 0, ..color};
+            // 2x2 at 24 bpp, bottom row first, mask rows padded to 4 bytes
+            let data = [0, 0, 0, 255, 255, 255, 0, 0, 255, 255, 255, 0, 0, 0, 0, 0];
+            let bitmap = icon_dib(2, 2, 24, &[], &data, &[0b0100_0000, 0, 0, 0, 0b1000_0000, 0, 0, 0]).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![clear(WHITE), BLACK], vec![BLACK, clear(WHITE)]]);
+
+            let bitmap = icon_dib(3, 1, 1, &[0x000000, 0xffffff], &[0b0100_0000, 0, 0, 0], &[0b0010_0000, 0, 0, 0]).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![BLACK, WHITE, clear(BLACK)]]);
+        }
+
+        #[test]
+        fn icon_32bpp_alpha_from_top_byte() {
+            let data = [0xff, 0xff, 0xff, 0x80, 0, 0, 0, 0];
+            let bitmap = icon_dib(2, 1, 32, &[], &data, &[0, 0, 0, 0]).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![Color {alpha: 0x80, ..WHITE}, Color {alpha: 0, ..BLACK}]]);
+
+            // Without any alpha in the top bytes, only the AND mask decides
+            let data = [0xff, 0xff, 0xff, 0, 0, 0, 0, 0];
+            let bitmap = icon_dib(2, 1, 32, &[], &data, &[0b0100_0000, 0, 0, 0]).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![WHITE, Color {alpha: 0, ..BLACK}]]);
+        }
+
+        #[test]
+        fn write_bmp_round_trips() {
+            // 3 pixels take 9 bytes, so every row is padded by 3
+            let color = |red: u8, green: u8, blue: u8| Color {red, green, blue, alpha: 255};
+            let bitmap = Bitmap {width: 3, height: 2, pixels: vec![
+                vec![color(255, 0, 0), color(0, 255, 0), color(0, 0, 255)],
+                vec![color(1, 2, 3), WHITE, BLACK]
+            ]};
+            let path = std::env::temp_dir().join(format!("term_viewer_round_trip_{}.bmp", std::process::id()));
+            bitmap.write_bmp(&path).unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(bytes.len(), 54 + 2 * 12);
+            let read = Bitmap::from_reader(&mut &bytes[..], Orientation::Auto, BLACK).unwrap();
+            assert_eq!((read.width, read.height), (3, 2));
+            assert_eq!(read.pixels, bitmap.pixels);
+        }
+    }
+}
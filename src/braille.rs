@@ -0,0 +1,45 @@
+pub mod braille {
+    use std::io::Write;
+
+    use crate::ansi::ansi::Color;
+    use crate::render::render::{self, CellFit, RenderOptions};
+
+    // U+2800 has no dots set, each dot adds its bit to the codepoint
+    const BLANK: u32 = 0x2800;
+    const DOT_BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+    // Draws the image centered in the region of term_height x term_width cells, returns the number of rows down to its bottom
+    pub fn render<W: Write>(pixels: &[Vec<Color>], term_height: usize, term_width: usize, options: &RenderOptions, writer: &mut W) -> std::io::Result<usize> {
+        let height = pixels.len();
+        let width = pixels.first().map_or(0, Vec::len);
+        if width == 0 || height == 0 {
+            return Ok(0);
+        }
+
+        let CellFit {x_step, y_step, x_start, y_start, width: columns, height: rows} = render::fit_cells(width, height, term_height, term_width, options);
+        let inset = render::center(columns, rows, term_height, term_width, &options.inset);
+        let (dot_width, dot_height) = (x_step / 2.0, y_step / 4.0);
+        for row in 0..rows {
+            render::set_row(writer, row, &inset)?;
+            let y = y_start + row as f64 * y_step;
+            let line: String = (0..columns).map(|column| {
+                let x = x_start + column as f64 * x_step;
+                let mut codepoint = BLANK;
+                for (dot_y, bits) in DOT_BITS.iter().enumerate() {
+                    for (dot_x, bit) in bits.iter().enumerate() {
+                        // The last cell may cover less than a full step of the image
+                        let dot_x = f64::min(x + dot_x as f64 * dot_width, (width - 1) as f64);
+                        let dot_y = f64::min(y + dot_y as f64 * dot_height, (height - 1) as f64);
+                        if render::sample(pixels, dot_x, dot_y, dot_width, dot_height, options).luma() >= options.threshold {
+                            codepoint |= bit;
+                        }
+                    }
+                }
+                char::from_u32(codepoint).unwrap_or(' ')
+            }).collect();
+            write!(writer, "{line}")?;
+        }
+
+        Ok(inset.top - options.inset.top + rows)
+    }
+}
@@ -1,50 +1,93 @@
-pub mod common {
-    use std::io::BufRead;
-
-    enum Endianess {
-        Little,
-        Big
-    }
-
-    fn slice_to_usize(bytes: &[u8], endianess: Endianess) -> usize {
-        if bytes.len() > 8 {
-            panic!("Slice len must be <= 8 bytes");
-        }
-
-        let mut usize = 0;
-        match endianess {
-            Endianess::Little => {
-                for (i, &byte) in bytes.iter().enumerate() {
-                    usize += (byte as usize) << i*8;
-                }
-            },
-            Endianess::Big => {
-                for (i, &byte) in bytes.iter().rev().enumerate() {
-                    usize += (byte as usize) << i*8;
-                }
-            }
-        }
-
-        usize
-    }
-
-    pub fn slice_to_usize_le(bytes: &[u8]) -> usize {
-        slice_to_usize(bytes, Endianess::Little)
-    }
-
-    pub fn slice_to_usize_be(bytes: &[u8]) -> usize {
-        slice_to_usize(bytes, Endianess::Big)
-    }
-
-    pub fn read_u32<R: BufRead>(reader: &mut R) -> std::io::Result<u32> {
-        let mut buf = [0; 4];
-        reader.read_exact(&mut buf)?;
-        Ok(slice_to_usize_le(&buf) as u32)
-    }
-    
-    pub fn read_u16<R: BufRead>(reader: &mut R) -> std::io::Result<u16> {
-        let mut buf = [0; 2];
-        reader.read_exact(&mut buf)?;
-        Ok(slice_to_usize_le(&buf) as u16)
-    }
-}
\ No newline at end of file
+pub mod common {
+    use std::io::{sink, BufRead, Error, ErrorKind, Read};
+
+    #[allow(dead_code)]
+    enum Endianess {
+        Little,
+        Big
+    }
+
+    fn slice_to_usize(bytes: &[u8], endianess: Endianess) -> usize {
+        if bytes.len() > 8 {
+            panic!("Slice len must be <= 8 bytes");
+        }
+
+        let mut usize = 0;
+        match endianess {
+            Endianess::Little => {
+                for (i, &byte) in bytes.iter().enumerate() {
+                    usize += (byte as usize) << (i * 8);
+                }
+            },
+            Endianess::Big => {
+                for (i, &byte) in bytes.iter().rev().enumerate() {
+                    usize += (byte as usize) << (i * 8);
+                }
+            }
+        }
+
+        usize
+    }
+
+    pub fn slice_to_usize_le(bytes: &[u8]) -> usize {
+        slice_to_usize(bytes, Endianess::Little)
+    }
+
+    pub fn slice_to_usize_be(bytes: &[u8]) -> usize {
+        slice_to_usize(bytes, Endianess::Big)
+    }
+
+    pub fn read_u32<R: BufRead>(reader: &mut R) -> std::io::Result<u32> {
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf)?;
+        Ok(slice_to_usize_le(&buf) as u32)
+    }
+    
+    pub fn read_u16<R: BufRead>(reader: &mut R) -> std::io::Result<u16> {
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf)?;
+        Ok(slice_to_usize_le(&buf) as u16)
+    }
+
+    // Limit from the QOI specification, guards against absurd allocations from corrupt headers
+    pub const MAX_PIXELS: usize = 400_000_000;
+
+    // Number of pixels of an image, if it stays within MAX_PIXELS
+    pub fn num_pixels(width: usize, height: usize) -> Option<usize> {
+        width.checked_mul(height).filter(|&num_pixels| num_pixels <= MAX_PIXELS)
+    }
+
+    // Rounds to the nearest 8 bit value instead of dropping the low byte
+    pub fn scale_16_to_8(value: u16) -> u8 {
+        ((value as u32 * 255 + 32767) / 65535) as u8
+    }
+
+    pub fn skip_bytes<R: BufRead>(reader: &mut R, num_bytes: usize) -> std::io::Result<()> {
+        let skipped = std::io::copy(&mut reader.by_ref().take(num_bytes as u64), &mut sink())?;
+        if skipped < num_bytes as u64 {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        }
+
+        Ok(())
+    }
+
+    const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    pub fn base64_encode(bytes: &[u8]) -> String {
+        let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+        for chunk in bytes.chunks(3) {
+            let triple = (chunk[0] as u32) << 16
+                | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+                | *chunk.get(2).unwrap_or(&0) as u32;
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    encoded.push(BASE64_ALPHABET[((triple >> (18 - 6 * i)) & 0x3f) as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+
+        encoded
+    }
+}
@@ -0,0 +1,61 @@
+pub mod error {
+    use std::fmt;
+    use std::io::Error;
+
+    pub type Result<T> = std::result::Result<T, ViewerError>;
+
+    #[derive(Debug)]
+    pub enum ViewerError {
+        // Valid file using a feature or format that isn't implemented
+        UnsupportedFormat(String),
+        Malformed {reason: String},
+        TerminalTooSmall,
+        Io(Error)
+    }
+
+    impl ViewerError {
+        pub fn unsupported(reason: impl Into<String>) -> Self {
+            ViewerError::UnsupportedFormat(reason.into())
+        }
+
+        pub fn malformed(reason: impl Into<String>) -> Self {
+            ViewerError::Malformed {reason: reason.into()}
+        }
+    }
+
+    impl fmt::Display for ViewerError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ViewerError::UnsupportedFormat(reason) => write!(f, "{reason}"),
+                ViewerError::Malformed {reason} => write!(f, "{reason}"),
+                ViewerError::TerminalTooSmall => write!(f, "Terminal too small"),
+                ViewerError::Io(err) => write!(f, "{err}")
+            }
+        }
+    }
+
+    impl std::error::Error for ViewerError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                ViewerError::Io(err) => Some(err),
+                _ => None
+            }
+        }
+    }
+
+    impl From<Error> for ViewerError {
+        fn from(err: Error) -> Self {
+            ViewerError::Io(err)
+        }
+    }
+
+    // Lets functions still returning std::io::Result propagate with ?, the kind stays reachable through get_ref
+    impl From<ViewerError> for Error {
+        fn from(err: ViewerError) -> Self {
+            match err {
+                ViewerError::Io(err) => err,
+                err => Error::other(err)
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+pub mod hexdump {
+    use std::fmt::Write;
+    use std::io::Read;
+
+    const BYTES_PER_LINE: usize = 16;
+    const MAX_DUMP_BYTES: u64 = 64 * 1024;
+
+    // xxd-style offset/hex/ASCII lines of the first MAX_DUMP_BYTES of the input
+    pub fn dump<R: Read>(reader: R) -> std::io::Result<Vec<String>> {
+        let mut bytes = Vec::new();
+        reader.take(MAX_DUMP_BYTES).read_to_end(&mut bytes)?;
+
+        let lines = bytes.chunks(BYTES_PER_LINE)
+            .enumerate()
+            .map(|(i, chunk)| format_line(i * BYTES_PER_LINE, chunk))
+            .collect();
+        Ok(lines)
+    }
+
+    fn format_line(offset: usize, bytes: &[u8]) -> String {
+        let mut line = format!("{offset:08x}:");
+        for (i, byte) in bytes.iter().enumerate() {
+            if i % 2 == 0 {
+                line.push(' ');
+            }
+            let _ = write!(line, "{byte:02x}");
+        }
+
+        // Pad a short last line so the ASCII column stays aligned
+        let full_width = BYTES_PER_LINE * 2 + BYTES_PER_LINE / 2;
+        let width = bytes.len() * 2 + bytes.len().div_ceil(2);
+        line.push_str(&" ".repeat(full_width - width + 2));
+
+        for &byte in bytes {
+            let printable = byte.is_ascii_graphic() || byte == b' ';
+            line.push(if printable { byte as char } else { '.' });
+        }
+
+        line
+    }
+}
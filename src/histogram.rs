@@ -0,0 +1,74 @@
+pub mod histogram {
+    use std::io::{stdout, BufWriter, Write};
+
+    use crate::ansi::ansi::{Color, ColorMode};
+
+    const NUM_BINS: usize = 256;
+    const BAR_HEIGHT: usize = 8;
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    pub struct Histogram {
+        red: [u32; NUM_BINS],
+        green: [u32; NUM_BINS],
+        blue: [u32; NUM_BINS],
+        luma: [u32; NUM_BINS]
+    }
+
+    impl Histogram {
+        pub fn new(pixels: &[Vec<Color>]) -> Self {
+            let mut histogram = Histogram {
+                red: [0; NUM_BINS],
+                green: [0; NUM_BINS],
+                blue: [0; NUM_BINS],
+                luma: [0; NUM_BINS]
+            };
+
+            for pixel in pixels.iter().flatten() {
+                histogram.red[pixel.red as usize] += 1;
+                histogram.green[pixel.green as usize] += 1;
+                histogram.blue[pixel.blue as usize] += 1;
+                histogram.luma[pixel.luma() as usize] += 1;
+            }
+
+            histogram
+        }
+
+        pub fn print(&self, term_width: usize, mode: ColorMode) -> std::io::Result<()> {
+            let mut writer = BufWriter::new(stdout().lock());
+            let width = term_width.clamp(1, NUM_BINS);
+
+            print_channel(&mut writer, "red", &self.red, Color {red: 255, green: 0, blue: 0, alpha: 255}, width, mode)?;
+            print_channel(&mut writer, "green", &self.green, Color {red: 0, green: 255, blue: 0, alpha: 255}, width, mode)?;
+            print_channel(&mut writer, "blue", &self.blue, Color {red: 0, green: 0, blue: 255, alpha: 255}, width, mode)?;
+            print_channel(&mut writer, "luma", &self.luma, Color {red: 200, green: 200, blue: 200, alpha: 255}, width, mode)?;
+            writer.flush()
+        }
+    }
+
+    fn print_channel<W: Write>(writer: &mut W, name: &str, bins: &[u32; NUM_BINS], color: Color, width: usize, mode: ColorMode) -> std::io::Result<()> {
+        // Merge neighbouring bins when the terminal is narrower than the bin count
+        let columns: Vec<u64> = (0..width)
+            .map(|col| bins[col * NUM_BINS / width..(col + 1) * NUM_BINS / width].iter().map(|&n| n as u64).sum())
+            .collect();
+        let max = columns.iter().copied().max().unwrap_or(0);
+        writeln!(writer, "{name} (peak: {max})")?;
+
+        // Column heights in eighths of a cell
+        let heights: Vec<usize> = columns.iter()
+            .map(|&n| (n * (BAR_HEIGHT * 8) as u64).checked_div(max).unwrap_or(0) as usize)
+            .collect();
+        for row in (0..BAR_HEIGHT).rev() {
+            for &height in &heights {
+                let level = height.saturating_sub(row * 8);
+                match level {
+                    0 => write!(writer, " ")?,
+                    1..=7 => color.print_char(BLOCKS[level - 1], mode, writer)?,
+                    _ => color.print_char(BLOCKS[7], mode, writer)?
+                }
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+pub mod ico {
+    use std::io::BufRead;
+
+    use crate::ansi::ansi::Color;
+    use crate::bitmap::bitmap::Bitmap;
+    use crate::common::common::{read_u16, read_u32};
+    use crate::png::png;
+    use crate::error::error::{self, ViewerError};
+
+    const ICON: u16 = 1;
+    const CURSOR: u16 = 2;
+
+    struct DirEntry {
+        width: usize,
+        height: usize,
+        size: u32,
+        offset: u32
+    }
+
+    impl DirEntry {
+        fn from_reader<R: BufRead>(reader: &mut R) -> error::Result<Self> {
+            let mut dimensions = [0; 4];
+            reader.read_exact(&mut dimensions)?;
+            // Color planes/bit count for icons, hotspot x/y for cursors
+            let _planes_or_hotspot_x = read_u16(reader)?;
+            let _bit_count_or_hotspot_y = read_u16(reader)?;
+            let size = read_u32(reader)?;
+            let offset = read_u32(reader)?;
+
+            // A dimension of 0 means 256 pixels
+            let to_dimension = |value: u8| if value == 0 { 256 } else { value as usize };
+            Ok(DirEntry {
+                width: to_dimension(dimensions[0]),
+                height: to_dimension(dimensions[1]),
+                size,
+                offset
+            })
+        }
+    }
+
+    // Decodes the largest image of an .ico or .cur file
+    pub fn decode<R: BufRead>(reader: &mut R, missing_color: Color) -> error::Result<Bitmap> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut header = &bytes[..];
+
+        let _reserved = read_u16(&mut header)?;
+        let resource_type = read_u16(&mut header)?;
+        if resource_type != ICON && resource_type != CURSOR {
+            return Err(ViewerError::unsupported("Not an icon or cursor file"));
+        }
+
+        let num_entries = read_u16(&mut header)?;
+        let mut largest: Option<DirEntry> = None;
+        for _ in 0..num_entries {
+            let entry = DirEntry::from_reader(&mut header)?;
+            if largest.as_ref().is_none_or(|l| entry.width * entry.height > l.width * l.height) {
+                largest = Some(entry);
+            }
+        }
+
+        let Some(entry) = largest else {
+            return Err(ViewerError::malformed("Icon file contains no images"));
+        };
+        let start = entry.offset as usize;
+        let end = start + entry.size as usize;
+        let Some(mut image) = bytes.get(start..end) else {
+            return Err(ViewerError::malformed("Icon image data out of bounds"));
+        };
+
+        if image.starts_with(&png::SIGNATURE) {
+            return png::decode(&mut image);
+        }
+        Bitmap::from_icon_dib(&mut image, missing_color)
+    }
+}
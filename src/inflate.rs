@@ -0,0 +1,291 @@
+pub mod inflate {
+    use crate::error::error::{self, ViewerError};
+
+    const MAX_BITS: usize = 15;
+    const NUM_FIXED_LITERALS: usize = 288;
+    const NUM_FIXED_DISTANCES: usize = 30;
+    const END_OF_BLOCK: u16 = 256;
+
+    const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+    const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+    const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+        6145, 8193, 12289, 16385, 24577];
+    const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+    // Order in which the code length code lengths of a dynamic block are stored
+    const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+    // Deflate packs bits starting at the least significant bit of each byte
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+        bit_buf: u32,
+        bit_count: u32
+    }
+
+    impl<'a> BitReader<'a> {
+        fn new(data: &'a [u8]) -> Self {
+            BitReader {data, pos: 0, bit_buf: 0, bit_count: 0}
+        }
+
+        fn bits(&mut self, num_bits: u32) -> error::Result<u32> {
+            while self.bit_count < num_bits {
+                let Some(&byte) = self.data.get(self.pos) else {
+                    return Err(ViewerError::malformed("Deflate stream ends early"));
+                };
+                self.pos += 1;
+                self.bit_buf |= (byte as u32) << self.bit_count;
+                self.bit_count += 8;
+            }
+
+            let value = self.bit_buf & ((1 << num_bits) - 1);
+            self.bit_buf >>= num_bits;
+            self.bit_count -= num_bits;
+            Ok(value)
+        }
+
+        // Stored blocks start at the next byte boundary
+        fn align_to_byte(&mut self) {
+            self.bit_buf = 0;
+            self.bit_count = 0;
+        }
+
+        fn bytes(&mut self, num_bytes: usize) -> error::Result<&'a [u8]> {
+            let Some(bytes) = self.data.get(self.pos..self.pos + num_bytes) else {
+                return Err(ViewerError::malformed("Deflate stream ends early"));
+            };
+            self.pos += num_bytes;
+            Ok(bytes)
+        }
+    }
+
+    // Canonical Huffman code, stored as the number of codes per length and the symbols sorted by code
+    struct Huffman {
+        counts: [u16; MAX_BITS + 1],
+        symbols: Vec<u16>
+    }
+
+    impl Huffman {
+        fn new(lengths: &[u8]) -> Self {
+            let mut counts = [0; MAX_BITS + 1];
+            for &length in lengths {
+                counts[length as usize] += 1;
+            }
+            counts[0] = 0;
+
+            let mut offsets = [0; MAX_BITS + 2];
+            for length in 1..=MAX_BITS {
+                offsets[length + 1] = offsets[length] + counts[length];
+            }
+            let mut symbols = vec![0; lengths.len()];
+            for (symbol, &length) in lengths.iter().enumerate() {
+                if length != 0 {
+                    symbols[offsets[length as usize] as usize] = symbol as u16;
+                    offsets[length as usize] += 1;
+                }
+            }
+
+            Huffman {counts, symbols}
+        }
+
+        // Codes are read bit by bit, first being the lowest code of the current length
+        fn decode(&self, reader: &mut BitReader) -> error::Result<u16> {
+            let (mut code, mut first, mut index) = (0usize, 0usize, 0usize);
+            for length in 1..=MAX_BITS {
+                code |= reader.bits(1)? as usize;
+                let count = self.counts[length] as usize;
+                if code < first + count {
+                    return Ok(self.symbols[index + code - first]);
+                }
+                index += count;
+                first = (first + count) << 1;
+                code <<= 1;
+            }
+
+            Err(ViewerError::malformed("Invalid Huffman code"))
+        }
+    }
+
+    // Decompresses a zlib stream, the Adler-32 checksum at its end is not verified. Decompressing
+    // stops with an error as soon as the output grows past max_len, so a small stream can't claim lots of memory
+    pub fn zlib_decompress(data: &[u8], max_len: usize) -> error::Result<Vec<u8>> {
+        let [cmf, flg, ..] = data[..] else {
+            return Err(ViewerError::malformed("zlib stream ends early"));
+        };
+        if cmf & 0xf != 8 || !(((cmf as u16) << 8) | flg as u16).is_multiple_of(31) {
+            return Err(ViewerError::malformed("Invalid zlib header"));
+        }
+        if flg & 0x20 != 0 {
+            return Err(ViewerError::unsupported("zlib preset dictionaries not supported"));
+        }
+
+        inflate(&data[2..], max_len)
+    }
+
+    fn inflate(data: &[u8], max_len: usize) -> error::Result<Vec<u8>> {
+        let mut reader = BitReader::new(data);
+        let mut out = Vec::new();
+        loop {
+            let last = reader.bits(1)? == 1;
+            match reader.bits(2)? {
+                0 => stored_block(&mut reader, &mut out, max_len)?,
+                1 => {
+                    let (literals, distances) = fixed_codes();
+                    compressed_block(&mut reader, &mut out, max_len, &literals, &distances)?;
+                },
+                2 => {
+                    let (literals, distances) = dynamic_codes(&mut reader)?;
+                    compressed_block(&mut reader, &mut out, max_len, &literals, &distances)?;
+                },
+                _ => return Err(ViewerError::malformed("Invalid deflate block type"))
+            }
+
+            if last {
+                return Ok(out);
+            }
+        }
+    }
+
+    fn too_long() -> ViewerError {
+        ViewerError::malformed("Decompressed data longer than expected")
+    }
+
+    fn stored_block(reader: &mut BitReader, out: &mut Vec<u8>, max_len: usize) -> error::Result<()> {
+        reader.align_to_byte();
+        let header = reader.bytes(4)?;
+        let length = u16::from_le_bytes([header[0], header[1]]);
+        let inverted_length = u16::from_le_bytes([header[2], header[3]]);
+        if length != !inverted_length {
+            return Err(ViewerError::malformed("Stored block length does not match its complement"));
+        }
+
+        if out.len() + length as usize > max_len {
+            return Err(too_long());
+        }
+        out.extend_from_slice(reader.bytes(length as usize)?);
+        Ok(())
+    }
+
+    fn fixed_codes() -> (Huffman, Huffman) {
+        let mut lengths = [0; NUM_FIXED_LITERALS];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+
+        (Huffman::new(&lengths), Huffman::new(&[5; NUM_FIXED_DISTANCES]))
+    }
+
+    fn dynamic_codes(reader: &mut BitReader) -> error::Result<(Huffman, Huffman)> {
+        let num_literals = reader.bits(5)? as usize + 257;
+        let num_distances = reader.bits(5)? as usize + 1;
+        let num_code_lengths = reader.bits(4)? as usize + 4;
+
+        let mut code_lengths = [0; 19];
+        for &symbol in CODE_LENGTH_ORDER.iter().take(num_code_lengths) {
+            code_lengths[symbol] = reader.bits(3)? as u8;
+        }
+        let code_length_code = Huffman::new(&code_lengths);
+
+        // Literal/length and distance code lengths form one sequence, repeats may cross between them
+        let mut lengths = Vec::with_capacity(num_literals + num_distances);
+        while lengths.len() < num_literals + num_distances {
+            let (length, repeat) = match code_length_code.decode(reader)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => {
+                    let Some(&previous) = lengths.last() else {
+                        return Err(ViewerError::malformed("Repeated code length without a previous length"));
+                    };
+                    (previous, 3 + reader.bits(2)? as usize)
+                },
+                17 => (0, 3 + reader.bits(3)? as usize),
+                _ => (0, 11 + reader.bits(7)? as usize)
+            };
+            if lengths.len() + repeat > num_literals + num_distances {
+                return Err(ViewerError::malformed("Code lengths exceed the number of codes"));
+            }
+            lengths.extend(std::iter::repeat_n(length, repeat));
+        }
+        if lengths[END_OF_BLOCK as usize] == 0 {
+            return Err(ViewerError::malformed("Missing end of block code"));
+        }
+
+        Ok((Huffman::new(&lengths[..num_literals]), Huffman::new(&lengths[num_literals..])))
+    }
+
+    fn compressed_block(reader: &mut BitReader, out: &mut Vec<u8>, max_len: usize, literals: &Huffman, distances: &Huffman) -> error::Result<()> {
+        loop {
+            let symbol = literals.decode(reader)?;
+            if symbol < END_OF_BLOCK {
+                if out.len() == max_len {
+                    return Err(too_long());
+                }
+                out.push(symbol as u8);
+                continue;
+            }
+            if symbol == END_OF_BLOCK {
+                return Ok(());
+            }
+
+            let index = (symbol - END_OF_BLOCK - 1) as usize;
+            if index >= LENGTH_BASE.len() {
+                return Err(ViewerError::malformed("Invalid length symbol"));
+            }
+            let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+            let index = distances.decode(reader)? as usize;
+            if index >= DISTANCE_BASE.len() {
+                return Err(ViewerError::malformed("Invalid distance symbol"));
+            }
+            let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+            if distance > out.len() {
+                return Err(ViewerError::malformed("Distance reaches before the start of the output"));
+            }
+            if out.len() + length > max_len {
+                return Err(too_long());
+            }
+
+            // Source and destination may overlap, so copy byte by byte
+            let start = out.len() - distance;
+            for i in 0..length {
+                out.push(out[start + i]);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // zlib.compress(b"abcabcabcabc hello hello") with the fixed Huffman codes of Z_FIXED
+        const FIXED: [u8; 21] = [0x78, 0x01, 0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x85, 0x8c, 0xd4, 0x9c, 0x9c, 0x7c, 0x08, 0x09, 0x00,
+            0x70, 0x12, 0x09, 0x01];
+
+        // zlib.compress of TEXT at level 9, a single block with dynamic Huffman codes
+        const DYNAMIC: [u8; 85] = [0x78, 0xda, 0xb5, 0xcb, 0xc7, 0x01, 0x80, 0x20, 0x10, 0x05, 0xd1, 0x56, 0x7e, 0x05, 0xd4, 0xe2, 0xc1,
+            0x06, 0x40, 0x49, 0x06, 0x56, 0xb2, 0x50, 0xbd, 0xdb, 0x84, 0xe7, 0x79, 0xb3, 0x3a, 0x8d, 0x58, 0xfd, 0x76, 0x42, 0x25, 0xea,
+            0x01, 0x86, 0x5e, 0x1c, 0xf5, 0x7e, 0x32, 0xa8, 0xe9, 0x84, 0xc2, 0xf9, 0x92, 0x73, 0x60, 0x27, 0x2b, 0xb0, 0xfe, 0x86, 0x17,
+            0xc9, 0xee, 0x1e, 0x50, 0x8c, 0xba, 0x2f, 0x0e, 0xc6, 0x37, 0xcd, 0x69, 0xea, 0x80, 0xcb, 0xc7, 0x4a, 0x89, 0x5f, 0x9b, 0xc5,
+            0x07, 0xb2, 0xfb, 0x3f, 0x0d];
+        const TEXT: &str = "The quick brown fox jumps over the lazy dog. The quick brown fox jumps over the lazy dog. \
+            The quick brown fox jumps over the lazy dog. Pack my box with five dozen liquor jugs.";
+
+        #[test]
+        fn fixed_huffman_block() {
+            assert_eq!(FIXED[2] >> 1 & 3, 1);
+            assert_eq!(zlib_decompress(&FIXED, 1024).unwrap(), b"abcabcabcabc hello hello");
+        }
+
+        #[test]
+        fn dynamic_huffman_block() {
+            assert_eq!(DYNAMIC[2] >> 1 & 3, 2);
+            assert_eq!(zlib_decompress(&DYNAMIC, 1024).unwrap(), TEXT.as_bytes());
+        }
+
+        #[test]
+        fn output_past_the_limit_is_an_error() {
+            assert_eq!(zlib_decompress(&FIXED, 24).unwrap().len(), 24);
+            assert!(matches!(zlib_decompress(&FIXED, 23), Err(ViewerError::Malformed {..})));
+            assert!(matches!(zlib_decompress(&FIXED, 0), Err(ViewerError::Malformed {..})));
+        }
+    }
+}
@@ -0,0 +1,48 @@
+pub mod kitty {
+    use std::io::Write;
+
+    use crate::ansi::ansi::Color;
+    use crate::common::common::base64_encode;
+    use crate::render::render::{self, PixelFit, RenderOptions};
+
+    // Payload limit of a single escape sequence set by the protocol
+    const MAX_CHUNK_LEN: usize = 4096;
+    // Transmitting again under the same id replaces the image, so slideshows don't pile up images
+    const IMAGE_ID: u32 = 1;
+
+    // Draws the image scaled to fit the region of term_height x term_width cells, returns the number of rows it covers
+    pub fn render<W: Write>(pixels: &[Vec<Color>], term_height: usize, term_width: usize, options: &RenderOptions, writer: &mut W) -> std::io::Result<usize> {
+        let height = pixels.len();
+        let width = pixels.first().map_or(0, Vec::len);
+        if width == 0 || height == 0 {
+            return Ok(0);
+        }
+
+        let PixelFit {step, width: out_width, height: out_height, rows} = render::fit_pixels(width, height, term_height, term_width, options);
+        let mut rgba = Vec::with_capacity(out_width * out_height * 4);
+        for y in 0..out_height {
+            for x in 0..out_width {
+                let color = render::sample(pixels, x as f64 * step, y as f64 * step, step, step, options);
+                rgba.extend_from_slice(&[color.red, color.green, color.blue, color.alpha]);
+            }
+        }
+        let encoded = base64_encode(&rgba);
+
+        render::set_row(writer, 0, &options.inset)?;
+        // Only the first chunk carries the control data, m=1 announces that more chunks follow.
+        // q=2 suppresses the terminal's responses, C=1 leaves the cursor in place
+        let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(MAX_CHUNK_LEN).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let more = (i + 1 < chunks.len()) as u8;
+            if i == 0 {
+                write!(writer, "\x1b_Ga=T,i={IMAGE_ID},f=32,s={out_width},v={out_height},q=2,C=1,m={more};")?;
+            } else {
+                write!(writer, "\x1b_Gm={more};")?;
+            }
+            writer.write_all(chunk)?;
+            write!(writer, "\x1b\\")?;
+        }
+
+        Ok(rows)
+    }
+}
@@ -0,0 +1,17 @@
+pub mod log {
+    use std::fmt::Display;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+    pub fn set_verbose(verbose: bool) {
+        VERBOSE.store(verbose, Ordering::Relaxed);
+    }
+
+    // Diagnostics go to stderr, so they never end up in piped image output
+    pub fn verbose(message: impl Display) {
+        if VERBOSE.load(Ordering::Relaxed) {
+            eprintln!("{message}");
+        }
+    }
+}
@@ -1,65 +1,633 @@
-mod bitmap;
-mod common;
-mod ansi;
-
-use std::path::Path;
-use std::io::Error;
-use std::fs::read_dir;
-use std::{env, thread};
-use std::time::{Duration, Instant};
-
-use bitmap::bitmap::Bitmap;
-
-const MILLIS_PER_FRAME: u64 = 33;
-const DURATION_PER_FRAME: Duration = Duration::from_millis(MILLIS_PER_FRAME);
-
-fn handle_dir(path: &Path, term_height: usize, term_width: usize) -> std::io::Result<()> {
-    let entries = read_dir(path)?;
-    let mut prev = None;
-    for entry in entries {
-        let start = Instant::now();
-        let dir_entry = entry?;
-        let entry_path = dir_entry.path();
-        let entry_metadata = entry_path.metadata()?;
-        if !entry_metadata.is_file() {
-            continue;
-        }
-
-        let curr_bitmap = handle_file(&entry_path, term_height, term_width, prev)?;
-        let end = Instant::now();
-        let time_spent = end.duration_since(start);
-        if let Some(remaining_time) = DURATION_PER_FRAME.checked_sub(time_spent) {
-            thread::sleep(remaining_time);
-        }
-        prev = Some(curr_bitmap);
-    }
-
-    Ok(())
-}
-
-fn handle_file(path: &Path, term_height: usize, term_width: usize, prev: Option<Bitmap>) -> std::io::Result<Bitmap> {
-    let bitmap = Bitmap::new(path)?;
-    bitmap.print(term_height, term_width, prev)?;
-    Ok(bitmap)
-}
-
-fn main() -> std::io::Result<()> {
-    let term_size = termsize::get().expect("Should not fail");
-    let term_height = term_size.rows as usize;
-    let term_width = term_size.cols as usize;
-    println!("height: {term_height}, width: {term_width}");
-    
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        return Err(Error::other("Usage: cargo run -- [dirname/filename]"));
-    }
-    
-    let path = Path::new(&args[1]);
-    let metadata = path.metadata()?;
-    if metadata.is_dir() {
-        handle_dir(&path, term_height, term_width)
-    } else {
-        handle_file(&path, term_height, term_width, None)?;
-        Ok(())
-    }
-}
+#![allow(clippy::module_inception)]
+
+mod bitmap;
+mod common;
+mod ansi;
+mod args;
+mod term;
+mod pager;
+mod hexdump;
+mod histogram;
+mod ico;
+mod render;
+mod tga;
+mod inflate;
+mod png;
+mod netpbm;
+mod qoi;
+mod settings;
+mod sixel;
+mod kitty;
+mod quantize;
+mod braille;
+mod ascii;
+mod error;
+mod log;
+
+use std::path::{Path, PathBuf};
+use std::fs::{read_dir, File};
+use std::io::{stdin, stdout, BufRead, BufReader, BufWriter, Error, ErrorKind, IsTerminal, Read, Write};
+use std::process::ExitCode;
+use std::{env, thread};
+use std::time::{Duration, Instant};
+
+use bitmap::bitmap::{Bitmap, Flip};
+use error::error::ViewerError;
+use args::args::{Config, HELP, STDIN_PATH, USAGE};
+use hexdump::hexdump::dump;
+use pager::pager::page;
+use histogram::histogram::Histogram;
+use term::term::{RawMode, read_key, read_key_timeout, supports_kitty_graphics, supports_sixel};
+use settings::settings::{Action, Keymap, Settings};
+use ansi::ansi::AlternateScreen;
+use render::render::{Backend, RenderOptions, Sampling};
+
+// Frames of a slideshow transition, including the final image
+const TRANSITION_FRAMES: usize = 8;
+
+// Thumbnails per page in the grid view
+const GRID_ROWS: usize = 3;
+const GRID_COLUMNS: usize = 4;
+
+// Largest magnification of the pan view, each zoom key press doubles or halves it
+const MAX_ZOOM: f64 = 16.0;
+
+const SUPPORTED_EXTENSIONS: [&str; 10] = ["bmp", "ico", "cur", "tga", "png", "pbm", "pgm", "ppm", "pnm", "qoi"];
+
+fn file_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+fn is_supported(path: &Path) -> bool {
+    file_extension(path).is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.as_str()))
+}
+
+// Depth-first with an explicit stack, each directory's images come before its subdirectories, both sorted by name.
+// Symlinked directories are not followed, so links pointing back up the tree can't loop
+fn collect_images(path: &Path, recursive: bool) -> std::io::Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match read_dir(&dir) {
+            Ok(entries) => entries,
+            // Only the directory that was asked for has to be readable
+            Err(err) if dir != path => {
+                log::log::verbose(format_args!("Skipping {}: {err}", dir.display()));
+                continue;
+            },
+            Err(err) => return Err(err)
+        };
+        let mut entry_paths = entries.map(|entry| entry.map(|entry| entry.path())).collect::<std::io::Result<Vec<_>>>()?;
+        entry_paths.sort();
+
+        let mut subdirs = Vec::new();
+        for entry_path in entry_paths {
+            // Entries whose metadata can't be read, like dangling symlinks, are skipped instead of failing the walk
+            if entry_path.is_file() && is_supported(&entry_path) {
+                images.push(entry_path);
+            } else if recursive && entry_path.symlink_metadata().is_ok_and(|metadata| metadata.is_dir()) {
+                subdirs.push(entry_path);
+            }
+        }
+        // Reversed, so the first subdirectory is popped first
+        dirs.extend(subdirs.into_iter().rev());
+    }
+
+    Ok(images)
+}
+
+fn handle_dir(path: &Path, term_height: usize, term_width: usize, config: &Config) -> std::io::Result<()> {
+    let images = collect_images(path, config.recursive)?;
+    if config.list {
+        let mut stdout = stdout().lock();
+        for image in &images {
+            writeln!(stdout, "{}", image.display())?;
+        }
+        return Ok(());
+    }
+    if stdout().is_terminal() {
+        writeln!(stdout(), "Found {} images, starting slideshow…", images.len())?;
+    }
+    // Playback can be stopped with q, but only keys from a terminal are read
+    let interactive = stdin().is_terminal();
+    let _raw_mode = if interactive { Some(RawMode::enable()?) } else { None };
+
+    // Slideshows stay on the fast nearest neighbor path unless --smooth asks for area averaging.
+    // --fast already trades detail for speed, so it keeps averaging over its sparser samples.
+    let mut options = config.render.clone();
+    if matches!(options.sampling, Sampling::Auto) && options.stride == 1 {
+        options.sampling = Sampling::Nearest;
+    }
+
+    let (mut term_height, mut term_width) = (term_height, term_width);
+    let mut prev: Option<Bitmap> = None;
+    'images: for image in &images {
+        // The previous image was laid out for the old size, so it can't be diffed against
+        if term::term::take_resize() {
+            (term_height, term_width) = render::render::region_size(&options.inset);
+            prev = None;
+        }
+        let mut start = Instant::now();
+        // Like in the grid view, one unreadable file doesn't end the slideshow
+        let curr_bitmap = match decode_file(image, config) {
+            Ok(bitmap) => bitmap,
+            Err(err) => {
+                eprintln!("{}: {err}, skipping", image.display());
+                // The message may have scrolled the last image, so the next one is drawn in full
+                prev = None;
+                continue;
+            }
+        };
+        let decode_time = start.elapsed();
+        // Transition frames count towards the image they lead to
+        let mut render_time = Duration::ZERO;
+        let mut bytes_written = 0;
+
+        // Transitions blend pixel by pixel, so images of different sizes are cut
+        let from = match config.transition {
+            Some(_) => prev.as_ref().filter(|prev_bitmap| prev_bitmap.width == curr_bitmap.width && prev_bitmap.height == curr_bitmap.height).cloned(),
+            None => None
+        };
+        if let (Some(transition), Some(from)) = (config.transition, from) {
+            for step in 1..TRANSITION_FRAMES {
+                let frame = from.transition(&curr_bitmap, transition, step as f64 / TRANSITION_FRAMES as f64);
+                let render_start = Instant::now();
+                bytes_written += frame.print(term_height, term_width, prev.take(), &options)?;
+                render_time += render_start.elapsed();
+                prev = Some(frame);
+                if wait_for_next_frame(start, interactive, config)? {
+                    break 'images;
+                }
+                start = Instant::now();
+            }
+        }
+
+        let render_start = Instant::now();
+        bytes_written += curr_bitmap.print(term_height, term_width, prev.take(), &options)?;
+        render_time += render_start.elapsed();
+        print_stats(image, decode_time, render_time, bytes_written, config);
+        if wait_for_next_frame(start, interactive, config)? {
+            break;
+        }
+        prev = Some(curr_bitmap);
+    }
+
+    Ok(())
+}
+
+// Keeps each frame on screen for the frame duration, returns whether the quit key was pressed meanwhile
+fn wait_for_next_frame(start: Instant, interactive: bool, config: &Config) -> std::io::Result<bool> {
+    let deadline = start + config.frame_duration;
+    if interactive {
+        return wait_for_quit(deadline, &config.settings.keys);
+    }
+
+    if let Some(remaining_time) = deadline.checked_duration_since(Instant::now()) {
+        thread::sleep(remaining_time);
+    }
+    Ok(false)
+}
+
+fn handle_grid(path: &Path, term_height: usize, term_width: usize, config: &Config) -> std::io::Result<()> {
+    let images = collect_images(path, config.recursive)?;
+    let (mut cell_height, mut cell_width) = grid_cell_size(term_height, term_width)?;
+    let page_size = GRID_ROWS * GRID_COLUMNS;
+    let num_pages = std::cmp::max(images.len().div_ceil(page_size), 1);
+    let page_images = |page: usize| &images[page * page_size..std::cmp::min((page + 1) * page_size, images.len())];
+
+    // Thumbnails are placed cell by cell, which only block characters allow
+    let mut options = config.render.clone();
+    options.backend = Backend::Blocks;
+
+    // Without a terminal to read keys from, only the first page is shown
+    if !stdin().is_terminal() || !stdout().is_terminal() {
+        return draw_grid_page(&mut BufWriter::new(stdout().lock()), page_images(0), (0, num_pages), (term_height, term_width), (cell_height, cell_width), &options, config);
+    }
+
+    let _raw_mode = RawMode::enable()?;
+    let _alternate_screen = AlternateScreen::enter()?;
+    let (mut term_height, mut term_width) = (term_height, term_width);
+    let mut page = 0;
+    let mut redraw = true;
+    loop {
+        if term::term::take_resize() {
+            (term_height, term_width) = render::render::region_size(&options.inset);
+            (cell_height, cell_width) = grid_cell_size(term_height, term_width)?;
+            redraw = true;
+        }
+        if redraw {
+            draw_grid_page(&mut BufWriter::new(stdout().lock()), page_images(page), (page, num_pages), (term_height, term_width), (cell_height, cell_width), &options, config)?;
+            redraw = false;
+        }
+
+        match config.settings.keys.action(read_key()?) {
+            Some(Action::Quit) => break,
+            Some(Action::PageDown | Action::Down | Action::Right) if page + 1 < num_pages => {
+                page += 1;
+                redraw = true;
+            },
+            Some(Action::PageUp | Action::Up | Action::Left) if page > 0 => {
+                page -= 1;
+                redraw = true;
+            },
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn grid_cell_size(term_height: usize, term_width: usize) -> std::io::Result<(usize, usize)> {
+    // Last row is reserved for the status line, each cell for a thumbnail with its label below
+    let cell_height = term_height.saturating_sub(1) / GRID_ROWS;
+    let cell_width = term_width / GRID_COLUMNS;
+    if cell_height < 2 || cell_width < 2 {
+        return Err(ViewerError::TerminalTooSmall.into());
+    }
+    Ok((cell_height, cell_width))
+}
+
+fn draw_grid_page<W: Write>(writer: &mut W, images: &[PathBuf], (page, num_pages): (usize, usize), (term_height, term_width): (usize, usize), (cell_height, cell_width): (usize, usize), options: &RenderOptions, config: &Config) -> std::io::Result<()> {
+    ansi::ansi::hide_cursor(writer)?;
+    render::render::clear_region(writer, term_height, term_width, &options.inset)?;
+
+    // One column between thumbnails keeps them apart
+    let thumbnail_height = cell_height - 1;
+    let thumbnail_width = cell_width - 1;
+    for (i, image) in images.iter().enumerate() {
+        let mut cell_options = options.clone();
+        cell_options.inset.top += i / GRID_COLUMNS * cell_height;
+        cell_options.inset.left += i % GRID_COLUMNS * cell_width;
+
+        let name = image.file_name().unwrap_or(image.as_os_str()).to_string_lossy();
+        // Unreadable files keep their place in the grid, with the error as label
+        let label = match decode_file(image, config) {
+            Ok(bitmap) => {
+                bitmap.render_blocks(thumbnail_height, thumbnail_width, None, &cell_options, writer)?;
+                name.to_string()
+            },
+            Err(err) => format!("{name}: {err}")
+        };
+        render::render::set_row(writer, thumbnail_height, &cell_options.inset)?;
+        write!(writer, "{}", label.chars().take(thumbnail_width).collect::<String>())?;
+    }
+
+    let keys = &config.settings.keys;
+    let status = format!("page {}/{} ({}/{}: page, {}: quit)", page + 1, num_pages, keys.page_up as char, keys.page_down as char, keys.quit as char);
+    render::render::set_row(writer, term_height - 1, &options.inset)?;
+    config.settings.status_color.print_bg(&status, options.color_mode, writer)?;
+    ansi::ansi::show_cursor(writer)?;
+    writer.flush()
+}
+
+// Returns whether the quit key was pressed before the deadline
+fn wait_for_quit(deadline: Instant, keys: &Keymap) -> std::io::Result<bool> {
+    while let Some(remaining_time) = deadline.checked_duration_since(Instant::now()) {
+        if let Some(Action::Quit) = keys.action(read_key_timeout(remaining_time)?) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path == Path::new(STDIN_PATH)
+}
+
+// Piped input has no extension, so its format is told by magic bytes. TGA has none, only
+// version 2 files are recognized by their footer
+fn detect_format(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [b'B', b'M', ..] => Some("bmp"),
+        [0x89, b'P', b'N', b'G', ..] => Some("png"),
+        [b'q', b'o', b'i', b'f', ..] => Some("qoi"),
+        [b'P', b'1'..=b'6', ..] => Some("pnm"),
+        [0, 0, 1 | 2, 0, ..] => Some("ico"),
+        _ if bytes.ends_with(b"TRUEVISION-XFILE.\0") => Some("tga"),
+        _ => None
+    }
+}
+
+fn decode<R: BufRead>(format: &str, reader: &mut R, config: &Config) -> error::error::Result<Bitmap> {
+    match format {
+        "ico" | "cur" => ico::ico::decode(reader, config.missing_color),
+        "tga" => tga::tga::decode(reader),
+        "png" => png::png::decode(reader),
+        "pbm" | "pgm" | "ppm" | "pnm" => netpbm::netpbm::decode(reader),
+        "qoi" => qoi::qoi::decode(reader),
+        _ => Bitmap::from_reader(reader, config.orientation, config.missing_color)
+    }
+}
+
+fn decode_file(path: &Path, config: &Config) -> error::error::Result<Bitmap> {
+    let mut bitmap = if is_stdin(path) {
+        let mut bytes = Vec::new();
+        stdin().lock().read_to_end(&mut bytes)?;
+        let Some(format) = detect_format(&bytes) else {
+            return Err(ViewerError::unsupported("Unrecognized image format on stdin"));
+        };
+        decode(format, &mut &bytes[..], config)?
+    } else {
+        let format = file_extension(path).unwrap_or_default();
+        decode(&format, &mut BufReader::new(File::open(path)?), config)?
+    };
+    log::log::verbose(format_args!("{}: {}x{} pixels", path.display(), bitmap.width, bitmap.height));
+
+    match config.flip {
+        Some(Flip::Horizontal) => bitmap.flip_horizontal(),
+        Some(Flip::Vertical) => bitmap.flip_vertical(),
+        None => {}
+    }
+    for _ in 0..config.rotation {
+        bitmap.rotate_90();
+    }
+    if let Some([x, y, width, height]) = config.crop {
+        bitmap = bitmap.crop(x, y, width, height)?;
+    }
+
+    Ok(bitmap)
+}
+
+fn handle_file(path: &Path, term_height: usize, term_width: usize, prev: Option<Bitmap>, config: &Config) -> error::error::Result<Bitmap> {
+    let start = Instant::now();
+    let bitmap = decode_file(path, config)?;
+    let decoded = Instant::now();
+    let bytes_written = match &config.output {
+        Some(output) => bitmap.print_to_file(term_height, term_width, &config.render, Path::new(output))?,
+        None => bitmap.print(term_height, term_width, prev, &config.render)?
+    };
+    print_stats(path, decoded - start, decoded.elapsed(), bytes_written, config);
+    Ok(bitmap)
+}
+
+fn print_stats(path: &Path, decode_time: Duration, render_time: Duration, bytes_written: usize, config: &Config) {
+    if config.stats {
+        eprintln!("{}: decoded in {decode_time:.1?}, rendered in {render_time:.1?}, {bytes_written} bytes written", path.display());
+    }
+}
+
+// Saves the image with the transformations and color adjustments of the options applied
+fn handle_save(path: &Path, output: &Path, config: &Config) -> std::io::Result<()> {
+    let mut bitmap = decode_file(path, config)?;
+    for row in bitmap.pixels.iter_mut() {
+        for pixel in row.iter_mut() {
+            *pixel = render::render::adjust(*pixel, &config.render);
+        }
+    }
+    bitmap.write_bmp(output)
+}
+
+fn handle_hex(path: &Path, term_height: usize, term_width: usize, config: &Config) -> std::io::Result<()> {
+    let lines = if is_stdin(path) { dump(stdin().lock())? } else { dump(File::open(path)?)? };
+    page(&lines, term_height, term_width, &config.render, &config.settings)
+}
+
+fn handle_histogram(path: &Path, term_width: usize, config: &Config) -> std::io::Result<()> {
+    let bitmap = decode_file(path, config)?;
+    Histogram::new(&bitmap.pixels).print(term_width, config.render.color_mode)
+}
+
+fn handle_pan(path: &Path, term_height: usize, term_width: usize, config: &Config) -> std::io::Result<()> {
+    if is_stdin(path) {
+        return Err(Error::other("--pan reads keys from stdin, so the image can't be piped"));
+    }
+    let bitmap = decode_file(path, config)?;
+    let _raw_mode = RawMode::enable()?;
+    let _alternate_screen = AlternateScreen::enter()?;
+
+    let (mut term_height, mut term_width) = (term_height, term_width);
+    let mut options = config.render.clone();
+    options.fit_height = true;
+    // Panning moves in terminal cells, which only block characters map to
+    options.backend = Backend::Blocks;
+    let mut redraw = true;
+    loop {
+        if term::term::take_resize() {
+            (term_height, term_width) = render::render::region_size(&options.inset);
+            redraw = true;
+        }
+        let x_pan_step = std::cmp::max(term_width / 2, 1);
+        let y_pan_step = std::cmp::max(term_height / 2, 1);
+        let (scaled_width, scaled_height) = render::render::fit_height_size(bitmap.width, bitmap.height, term_height, &options);
+        let max_x_offset = scaled_width.saturating_sub(term_width);
+        let max_y_offset = scaled_height.saturating_sub(term_height);
+        options.x_offset = std::cmp::min(options.x_offset, max_x_offset);
+        options.y_offset = std::cmp::min(options.y_offset, max_y_offset);
+        if redraw {
+            bitmap.print(term_height, term_width, None, &options)?;
+            redraw = false;
+        }
+
+        match config.settings.keys.action(read_key()?) {
+            Some(Action::Quit) => break,
+            Some(Action::Left) if options.x_offset > 0 => {
+                options.x_offset = options.x_offset.saturating_sub(x_pan_step);
+                redraw = true;
+            },
+            Some(Action::Right) if options.x_offset < max_x_offset => {
+                options.x_offset = std::cmp::min(options.x_offset + x_pan_step, max_x_offset);
+                redraw = true;
+            },
+            Some(Action::Up) if options.y_offset > 0 => {
+                options.y_offset = options.y_offset.saturating_sub(y_pan_step);
+                redraw = true;
+            },
+            Some(Action::Down) if options.y_offset < max_y_offset => {
+                options.y_offset = std::cmp::min(options.y_offset + y_pan_step, max_y_offset);
+                redraw = true;
+            },
+            Some(Action::ZoomIn) if options.zoom < MAX_ZOOM => {
+                zoom(&mut options, 2.0, term_height, term_width);
+                redraw = true;
+            },
+            Some(Action::ZoomOut) if options.zoom > 1.0 => {
+                zoom(&mut options, 0.5, term_height, term_width);
+                redraw = true;
+            },
+            Some(Action::CopyColor) => {
+                // Shown on the last row until the next redraw
+                let color = bitmap.dominant_color();
+                let mut stdout = stdout().lock();
+                ansi::ansi::copy_to_clipboard(&color.to_hex(), &mut stdout)?;
+                render::render::set_row(&mut stdout, term_height - 1, &options.inset)?;
+                color.print(options.color_mode, &mut stdout)?;
+                write!(stdout, " {} copied to clipboard", color.to_hex())?;
+                stdout.flush()?;
+            },
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Scales the pan view by factor, keeping the cell at the center of the terminal in place
+fn zoom(options: &mut RenderOptions, factor: f64, term_height: usize, term_width: usize) {
+    options.zoom = (options.zoom * factor).clamp(1.0, MAX_ZOOM);
+    let scale = |offset: usize, cells: usize| {
+        let center = cells as f64 / 2.0;
+        f64::max((offset as f64 + center) * factor - center, 0.0) as usize
+    };
+    options.x_offset = scale(options.x_offset, term_width);
+    options.y_offset = scale(options.y_offset, term_height);
+}
+
+// Falls back to block characters if the terminal doesn't answer that it supports the requested graphics.
+// The terminal can only be asked if it is attached to both ends, otherwise --sixel and --kitty are trusted
+fn check_graphics_support(options: &mut RenderOptions) -> std::io::Result<()> {
+    if !stdin().is_terminal() || !stdout().is_terminal() {
+        return Ok(());
+    }
+    let supported = match options.backend {
+        Backend::Blocks | Backend::Braille | Backend::Ascii => true,
+        Backend::Sixel => supports_sixel()?,
+        Backend::Kitty => supports_kitty_graphics()?
+    };
+    if !supported {
+        eprintln!("Terminal does not support the requested graphics, falling back to block characters");
+        options.backend = Backend::Blocks;
+    }
+    Ok(())
+}
+
+fn run() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let Some(mut config) = Config::parse(&args, Settings::load())? else {
+        return writeln!(stdout(), "{USAGE}\n\n{HELP}");
+    };
+    log::log::set_verbose(config.verbose);
+    let (term_height, term_width) = term::term::size();
+    log::log::verbose(format_args!("terminal height: {term_height}, width: {term_width}"));
+    term::term::watch_resize()?;
+
+    // Everything below renders into the region left free by the inset
+    let (term_height, term_width) = render::render::region_size(&config.render.inset);
+    
+    let path = Path::new(&config.path);
+    if !is_stdin(path) && path.metadata()?.is_dir() {
+        if config.output.is_some() || config.save.is_some() {
+            Err(Error::other("--output and --save expect a single image"))
+        } else if config.grid && !config.list {
+            handle_grid(path, term_height, term_width, &config)
+        } else {
+            // Only the slideshow draws with the requested backend, --list and --grid never query the terminal
+            if !config.list {
+                check_graphics_support(&mut config.render)?;
+            }
+            handle_dir(path, term_height, term_width, &config)
+        }
+    } else if config.grid {
+        Err(Error::other("--grid expects a directory"))
+    } else if config.list {
+        writeln!(stdout(), "{}", path.display())
+    } else if let Some(save) = &config.save {
+        handle_save(path, Path::new(save), &config)
+    } else if config.hex {
+        handle_hex(path, term_height, term_width, &config)
+    } else if config.histogram {
+        handle_histogram(path, term_width, &config)
+    } else if config.pan {
+        handle_pan(path, term_height, term_width, &config)
+    } else {
+        if config.output.is_none() {
+            check_graphics_support(&mut config.render)?;
+        }
+        match handle_file(path, term_height, term_width, None, &config) {
+            Ok(_) => Ok(()),
+            // Files that aren't supported images can still be inspected, stdin is already consumed by then
+            Err(err @ (ViewerError::UnsupportedFormat(_) | ViewerError::Malformed {..})) if !is_stdin(path) && config.output.is_none() => {
+                eprintln!("{}: {err}, showing a hexdump instead", path.display());
+                handle_hex(path, term_height, term_width, &config)
+            },
+            Err(err) => Err(err.into())
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    term::term::install_panic_hook();
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        // Like other Unix tools, exit quietly once the reading end of a pipe is gone
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_cells_follow_the_terminal_shape() {
+        // Wide terminal, the last row is left for the status line
+        assert_eq!(grid_cell_size(31, 120).unwrap(), (10, 30));
+        // Tall terminal
+        assert_eq!(grid_cell_size(61, 40).unwrap(), (20, 10));
+        assert!(grid_cell_size(6, 120).is_err());
+        assert!(grid_cell_size(31, 7).is_err());
+    }
+
+    #[test]
+    fn decode_errors_keep_their_kind() {
+        let config = Config::parse(&["term_viewer".to_string(), "image".to_string()], Settings::default()).unwrap().unwrap();
+        let mut png = png::png::SIGNATURE.to_vec();
+        png.extend_from_slice(&[0, 0, 0, 5, b'I', b'H', b'D', b'R', 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(matches!(decode("png", &mut &png[..], &config), Err(ViewerError::Malformed {..})));
+        assert!(matches!(decode("pgm", &mut &b"P5 2 2 0\n"[..], &config), Err(ViewerError::Malformed {..})));
+        assert!(matches!(decode("qoi", &mut &[0u8; 14][..], &config), Err(ViewerError::UnsupportedFormat(_))));
+        assert!(matches!(decode("bmp", &mut &b"BM"[..], &config), Err(ViewerError::Io(_))));
+    }
+
+    #[test]
+    fn grid_page_places_cells_row_by_row() {
+        let config = Config::parse(&["term_viewer".to_string(), "images".to_string()], Settings::default()).unwrap().unwrap();
+        // Missing files still get their cell, labeled with the error
+        let images: Vec<PathBuf> = ["a", "b", "c", "d", "e", "f"].iter().map(|name| PathBuf::from(format!("/nonexistent/{name}.bmp"))).collect();
+        let mut output = Vec::new();
+        draw_grid_page(&mut output, &images, (0, 1), (31, 120), (10, 30), &config.render, &config).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        // Labels go below the 9 rows of each thumbnail, four cells per row
+        assert!(output.contains("\x1b[10;1Ha.bmp: "));
+        assert!(output.contains("\x1b[10;91Hd.bmp: "));
+        assert!(output.contains("\x1b[20;1He.bmp: "));
+        assert!(output.contains("\x1b[20;31Hf.bmp: "));
+        assert!(output.contains("\x1b[31;1H"));
+    }
+
+    // Every sample image in test_images/golden is rendered by each backend at a fixed size and compared
+    // with its NAME.BACKEND.ans file. UPDATE_GOLDEN=1 rewrites the .ans files instead
+    #[test]
+    fn golden_output() {
+        let config = Config::parse(&["term_viewer".to_string(), "image".to_string()], Settings::default()).unwrap().unwrap();
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_images/golden");
+        let update = env::var("UPDATE_GOLDEN").is_ok_and(|value| value == "1");
+        let backends = [("blocks", Backend::Blocks), ("ascii", Backend::Ascii), ("braille", Backend::Braille), ("sixel", Backend::Sixel)];
+
+        let images = collect_images(&dir, false).unwrap();
+        assert!(!images.is_empty());
+        let mut mismatches = Vec::new();
+        for image in &images {
+            let bitmap = decode_file(image, &config).unwrap();
+            for (name, backend) in &backends {
+                // The cell size is fixed too, so the output does not depend on the terminal running the tests
+                let options = RenderOptions {backend: *backend, cell_size: Some((10, 20)), ..RenderOptions::default()};
+                let rendered = bitmap.render_to_string(12, 32, &options).unwrap();
+                let golden = image.with_extension(format!("{}.{name}.ans", file_extension(image).unwrap()));
+                if update {
+                    std::fs::write(&golden, rendered).unwrap();
+                } else if std::fs::read_to_string(&golden).ok().as_deref() != Some(rendered.as_str()) {
+                    mismatches.push(golden.display().to_string());
+                }
+            }
+        }
+        assert!(mismatches.is_empty(), "Output differs from, or is missing, {mismatches:?}. Rerun with UPDATE_GOLDEN=1 if the change is intended");
+    }
+}
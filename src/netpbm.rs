@@ -0,0 +1,194 @@
+pub mod netpbm {
+    use std::io::{BufRead, Error, ErrorKind};
+
+    use crate::ansi::ansi::Color;
+    use crate::bitmap::bitmap::Bitmap;
+    use crate::common::common::num_pixels;
+    use crate::error::error::{self, ViewerError};
+
+    const MAX_VALUE_LIMIT: usize = 65535;
+
+    #[derive(Copy, Clone)]
+    enum Kind {
+        Pbm,
+        Pgm,
+        Ppm
+    }
+
+    // P1-P3 store samples as ASCII decimals, P4-P6 as raw bytes
+    pub fn decode<R: BufRead>(reader: &mut R) -> error::Result<Bitmap> {
+        let mut magic = [0; 2];
+        reader.read_exact(&mut magic)?;
+        let (kind, binary) = match &magic {
+            b"P1" => (Kind::Pbm, false),
+            b"P2" => (Kind::Pgm, false),
+            b"P3" => (Kind::Ppm, false),
+            b"P4" => (Kind::Pbm, true),
+            b"P5" => (Kind::Pgm, true),
+            b"P6" => (Kind::Ppm, true),
+            _ => return Err(ViewerError::unsupported("File does not start with a Netpbm magic number"))
+        };
+
+        let width = read_number(reader)?;
+        let height = read_number(reader)?;
+        let max_value = match kind {
+            Kind::Pbm => 1,
+            _ => read_number(reader)?
+        };
+        if max_value == 0 || max_value > MAX_VALUE_LIMIT {
+            return Err(ViewerError::malformed(format!("Invalid Netpbm maximum value {max_value}")));
+        }
+        if num_pixels(width, height).is_none() {
+            return Err(ViewerError::malformed("Netpbm image too large"));
+        }
+        // Exactly one whitespace character separates the header from binary samples
+        if binary && !read_byte(reader)?.is_ascii_whitespace() {
+            return Err(ViewerError::malformed("Missing whitespace after Netpbm header"));
+        }
+
+        let mut pixels = Vec::with_capacity(height);
+        for _ in 0..height {
+            let line = match (kind, binary) {
+                (Kind::Pbm, true) => read_packed_line(reader, width)?,
+                (Kind::Pbm, false) => {
+                    let mut line = Vec::with_capacity(width);
+                    for _ in 0..width {
+                        line.push(bit_to_color(read_bit(reader)?));
+                    }
+                    line
+                },
+                (kind, binary) => {
+                    let mut line = Vec::with_capacity(width);
+                    for _ in 0..width {
+                        let mut read = || read_sample(reader, binary, max_value).map(|sample| scale(sample, max_value));
+                        let color = match kind {
+                            Kind::Ppm => Color {red: read()?, green: read()?, blue: read()?, alpha: 255},
+                            _ => {
+                                let gray = read()?;
+                                Color {red: gray, green: gray, blue: gray, alpha: 255}
+                            }
+                        };
+                        line.push(color);
+                    }
+                    line
+                }
+            };
+            pixels.push(line);
+        }
+
+        Ok(Bitmap {width, height, pixels})
+    }
+
+    fn read_byte<R: BufRead>(reader: &mut R) -> error::Result<u8> {
+        let mut byte = [0; 1];
+        reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn peek_byte<R: BufRead>(reader: &mut R) -> error::Result<Option<u8>> {
+        Ok(reader.fill_buf()?.first().copied())
+    }
+
+    // Comments start with '#' and run until the end of the line, they may appear wherever whitespace may
+    fn skip_whitespace_and_comments<R: BufRead>(reader: &mut R) -> error::Result<()> {
+        while let Some(byte) = peek_byte(reader)? {
+            if byte == b'#' {
+                reader.read_until(b'\n', &mut Vec::new())?;
+            } else if byte.is_ascii_whitespace() {
+                reader.consume(1);
+            } else {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_number<R: BufRead>(reader: &mut R) -> error::Result<usize> {
+        skip_whitespace_and_comments(reader)?;
+        let mut number: Option<usize> = None;
+        while let Some(byte) = peek_byte(reader)? {
+            if !byte.is_ascii_digit() {
+                break;
+            }
+            reader.consume(1);
+            number = number.unwrap_or(0)
+                .checked_mul(10)
+                .and_then(|number| number.checked_add((byte - b'0') as usize));
+            if number.is_none() {
+                return Err(ViewerError::malformed("Number in Netpbm file too large"));
+            }
+        }
+
+        match number {
+            Some(number) => Ok(number),
+            None if peek_byte(reader)?.is_none() => Err(Error::from(ErrorKind::UnexpectedEof).into()),
+            None => Err(ViewerError::malformed("Expected a number in Netpbm file"))
+        }
+    }
+
+    // ASCII bitmaps may omit the whitespace between pixels
+    fn read_bit<R: BufRead>(reader: &mut R) -> error::Result<bool> {
+        skip_whitespace_and_comments(reader)?;
+        match read_byte(reader)? {
+            b'0' => Ok(false),
+            b'1' => Ok(true),
+            _ => Err(ViewerError::malformed("Expected 0 or 1 in PBM file"))
+        }
+    }
+
+    // Binary bitmaps pack 8 pixels per byte, each line starts on a new byte
+    fn read_packed_line<R: BufRead>(reader: &mut R, width: usize) -> error::Result<Vec<Color>> {
+        let mut bytes = vec![0; width.div_ceil(8)];
+        reader.read_exact(&mut bytes)?;
+        Ok((0..width).map(|x| bit_to_color(bytes[x / 8] & (0x80 >> (x % 8)) != 0)).collect())
+    }
+
+    // Binary samples take two big-endian bytes if the maximum value does not fit into one
+    fn read_sample<R: BufRead>(reader: &mut R, binary: bool, max_value: usize) -> error::Result<usize> {
+        let sample = match binary {
+            false => read_number(reader)?,
+            true if max_value > 255 => {
+                let mut bytes = [0; 2];
+                reader.read_exact(&mut bytes)?;
+                u16::from_be_bytes(bytes) as usize
+            },
+            true => read_byte(reader)? as usize
+        };
+        if sample > max_value {
+            return Err(ViewerError::malformed(format!("Sample {sample} exceeds maximum value {max_value}")));
+        }
+
+        Ok(sample)
+    }
+
+    fn scale(sample: usize, max_value: usize) -> u8 {
+        ((sample * 255 + max_value / 2) / max_value) as u8
+    }
+
+    // A set bit is black
+    fn bit_to_color(bit: bool) -> Color {
+        let value = if bit { 0 } else { 255 };
+        Color {red: value, green: value, blue: value, alpha: 255}
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn ascii_and_binary_samples() {
+            let gray = |value| Color {red: value, green: value, blue: value, alpha: 255};
+            let bitmap = decode(&mut &b"P2\n# comment\n2 1\n4\n0 2"[..]).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![gray(0), gray(128)]]);
+
+            let bitmap = decode(&mut &b"P5 2 1 255\n\x00\xff"[..]).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![gray(0), gray(255)]]);
+        }
+
+        #[test]
+        fn oversized_header_is_an_error() {
+            assert!(matches!(decode(&mut &b"P5 99999999999999 99999999 255\n"[..]), Err(ViewerError::Malformed {..})));
+        }
+    }
+}
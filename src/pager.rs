@@ -0,0 +1,88 @@
+pub mod pager {
+    use std::io::{stdin, stdout, BufWriter, IsTerminal, Write};
+
+    use crate::ansi::ansi::AlternateScreen;
+    use crate::render::render::{self, RenderOptions};
+    use crate::settings::settings::{Action, Settings};
+    use crate::term::term::{self, RawMode};
+
+    pub fn page(lines: &[String], term_height: usize, term_width: usize, options: &RenderOptions, settings: &Settings) -> std::io::Result<()> {
+        if !stdin().is_terminal() || !stdout().is_terminal() {
+            let mut writer = BufWriter::new(stdout().lock());
+            for line in lines {
+                writeln!(writer, "{line}")?;
+            }
+            return writer.flush();
+        }
+
+        let _raw_mode = RawMode::enable()?;
+        // Declared before the writer so everything buffered is flushed before the screen is left
+        let _alternate_screen = AlternateScreen::enter()?;
+        let mut writer = BufWriter::new(stdout().lock());
+        let (mut term_height, mut term_width) = (term_height, term_width);
+        let mut top = 0;
+        let mut redraw = true;
+        loop {
+            if term::take_resize() {
+                (term_height, term_width) = render::region_size(&options.inset);
+                redraw = true;
+            }
+            // Last line is reserved for the status line
+            let num_printable_lines = std::cmp::max(term_height.saturating_sub(1), 1);
+            let last_top = lines.len().saturating_sub(num_printable_lines);
+            top = std::cmp::min(top, last_top);
+            if redraw {
+                draw(&mut writer, lines, top, (num_printable_lines, term_width), options, settings)?;
+                redraw = false;
+            }
+
+            match settings.keys.action(term::read_key()?) {
+                Some(Action::Quit) => break,
+                Some(Action::Up) if top > 0 => {
+                    top -= 1;
+                    redraw = true;
+                },
+                Some(Action::Down) if top < last_top => {
+                    top += 1;
+                    redraw = true;
+                },
+                Some(Action::PageUp) if top > 0 => {
+                    top = top.saturating_sub(num_printable_lines);
+                    redraw = true;
+                },
+                Some(Action::PageDown) if top < last_top => {
+                    top = std::cmp::min(top + num_printable_lines, last_top);
+                    redraw = true;
+                },
+                Some(Action::Top) if top > 0 => {
+                    top = 0;
+                    redraw = true;
+                },
+                Some(Action::Bottom) if top < last_top => {
+                    top = last_top;
+                    redraw = true;
+                },
+                _ => {}
+            }
+        }
+
+        writer.flush()
+    }
+
+    fn draw<W: Write>(writer: &mut W, lines: &[String], top: usize, (num_printable_lines, term_width): (usize, usize), options: &RenderOptions, settings: &Settings) -> std::io::Result<()> {
+        // Printable lines and the status line
+        render::clear_region(writer, num_printable_lines + 1, term_width, &options.inset)?;
+        for (row, line) in lines.iter().skip(top).take(num_printable_lines).enumerate() {
+            render::set_row(writer, row, &options.inset)?;
+            write!(writer, "{line}")?;
+        }
+
+        let bottom = std::cmp::min(top + num_printable_lines, lines.len());
+        render::set_row(writer, num_printable_lines, &options.inset)?;
+        let keys = &settings.keys;
+        let status = format!("lines {}-{}/{} ({}/{}: scroll, {}/{}: page, {}/{}: top/bottom, {}: quit)", top + 1, bottom, lines.len(),
+            keys.up as char, keys.down as char, keys.page_up as char, keys.page_down as char, keys.top as char, keys.bottom as char, keys.quit as char);
+        settings.status_color.print_bg(&status, options.color_mode, writer)?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,418 @@
+pub mod png {
+    use std::io::{BufRead, ErrorKind, Read};
+
+    use crate::ansi::ansi::Color;
+    use crate::bitmap::bitmap::Bitmap;
+    use crate::common::common::{num_pixels, scale_16_to_8, slice_to_usize_be};
+    use crate::inflate::inflate::zlib_decompress;
+    use crate::error::error::{self, ViewerError};
+    use crate::log::log;
+
+    pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    // Larger chunks are not allowed by the specification
+    const MAX_CHUNK_LEN: usize = 1 << 31;
+    // Compressed text is only shown, so longer text is skipped rather than inflated without bound
+    const MAX_TEXT_LEN: usize = 1 << 16;
+
+    const GRAYSCALE: u8 = 0;
+    const TRUECOLOR: u8 = 2;
+    const INDEXED: u8 = 3;
+    const GRAYSCALE_ALPHA: u8 = 4;
+    const TRUECOLOR_ALPHA: u8 = 6;
+
+    // Adam7 passes as (x start, y start, x step, y step). Non-interlaced images are a single pass
+    const ADAM7: [(usize, usize, usize, usize); 7] = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+    const NO_INTERLACE: [(usize, usize, usize, usize); 1] = [(0, 0, 1, 1)];
+
+    struct Header {
+        width: usize,
+        height: usize,
+        bit_depth: u8,
+        color_type: u8,
+        interlaced: bool
+    }
+
+    impl Header {
+        fn from_chunk(data: &[u8]) -> error::Result<Self> {
+            if data.len() != 13 {
+                return Err(ViewerError::malformed("Invalid IHDR chunk length"));
+            }
+            let width = slice_to_usize_be(&data[0..4]);
+            let height = slice_to_usize_be(&data[4..8]);
+            let [bit_depth, color_type, compression, filter, interlace] = data[8..13] else {
+                unreachable!()
+            };
+            if num_pixels(width, height).is_none() {
+                return Err(ViewerError::malformed("PNG image too large"));
+            }
+
+            let valid_depth = match color_type {
+                GRAYSCALE => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+                INDEXED => matches!(bit_depth, 1 | 2 | 4 | 8),
+                TRUECOLOR | GRAYSCALE_ALPHA | TRUECOLOR_ALPHA => matches!(bit_depth, 8 | 16),
+                _ => return Err(ViewerError::unsupported(format!("Unsupported PNG color type {color_type}")))
+            };
+            if !valid_depth {
+                return Err(ViewerError::malformed(format!("Invalid bit depth {bit_depth} for PNG color type {color_type}")));
+            }
+            if compression != 0 || filter != 0 || interlace > 1 {
+                return Err(ViewerError::unsupported("Unsupported PNG compression, filter or interlace method"));
+            }
+
+            Ok(Header {width, height, bit_depth, color_type, interlaced: interlace == 1})
+        }
+
+        fn channels(&self) -> usize {
+            match self.color_type {
+                TRUECOLOR => 3,
+                GRAYSCALE_ALPHA => 2,
+                TRUECOLOR_ALPHA => 4,
+                _ => 1
+            }
+        }
+
+        fn bits_per_pixel(&self) -> usize {
+            self.channels() * self.bit_depth as usize
+        }
+
+        fn passes(&self) -> &'static [(usize, usize, usize, usize)] {
+            if self.interlaced { &ADAM7 } else { &NO_INTERLACE }
+        }
+
+        // Size in pixels of each pass, empty passes are skipped
+        fn pass_sizes(&self) -> impl Iterator<Item = ((usize, usize, usize, usize), usize, usize)> + '_ {
+            self.passes().iter().map(|&pass @ (x_start, y_start, x_step, y_step)| {
+                (pass, self.width.saturating_sub(x_start).div_ceil(x_step), self.height.saturating_sub(y_start).div_ceil(y_step))
+            }).filter(|&(_, pass_width, pass_height)| pass_width > 0 && pass_height > 0)
+        }
+
+        // Each line of each pass starts with its filter type byte
+        fn data_len(&self) -> usize {
+            self.pass_sizes().map(|(_, pass_width, pass_height)| pass_height * (1 + (pass_width * self.bits_per_pixel()).div_ceil(8))).sum()
+        }
+    }
+
+    pub fn decode<R: BufRead>(reader: &mut R) -> error::Result<Bitmap> {
+        let mut signature = [0; 8];
+        reader.read_exact(&mut signature)?;
+        if signature != SIGNATURE {
+            return Err(ViewerError::unsupported("File does not start with PNG signature"));
+        }
+
+        let mut header = None;
+        let mut palette = Vec::new();
+        let mut transparency = Vec::new();
+        let mut compressed = Vec::new();
+        loop {
+            let (kind, data) = read_chunk(reader).map_err(truncated)?;
+            match &kind {
+                b"IHDR" => header = Some(Header::from_chunk(&data)?),
+                b"PLTE" => palette = data.chunks_exact(3).map(|rgb| Color {red: rgb[0], green: rgb[1], blue: rgb[2], alpha: 255}).collect(),
+                b"tRNS" => transparency = data,
+                b"IDAT" => compressed.extend_from_slice(&data),
+                b"IEND" => break,
+                b"tEXt" | b"zTXt" | b"iTXt" => match read_text(&kind, &data) {
+                    Ok((keyword, text)) => log::verbose(format_args!("PNG text {keyword}: {text}")),
+                    // Text is not needed for displaying, so a broken chunk is only reported
+                    Err(err) => log::verbose(format_args!("Skipping {} chunk: {err}", String::from_utf8_lossy(&kind)))
+                },
+                // Remaining chunks are ancillary and not needed for displaying
+                _ => {}
+            }
+        }
+        let Some(header) = header else {
+            return Err(ViewerError::malformed("PNG file has no IHDR chunk"));
+        };
+        if header.color_type == INDEXED {
+            for (color, &alpha) in palette.iter_mut().zip(&transparency) {
+                color.alpha = alpha;
+            }
+        }
+
+        let data = zlib_decompress(&compressed, header.data_len())?;
+        let pixels = read_pixels(&data, &header, &palette, &transparency)?;
+        Ok(Bitmap {width: header.width, height: header.height, pixels})
+    }
+
+    fn read_chunk<R: BufRead>(reader: &mut R) -> error::Result<([u8; 4], Vec<u8>)> {
+        let mut length = [0; 4];
+        reader.read_exact(&mut length)?;
+        let length = slice_to_usize_be(&length);
+        if length >= MAX_CHUNK_LEN {
+            return Err(ViewerError::malformed("PNG chunk too large"));
+        }
+
+        let mut kind = [0; 4];
+        reader.read_exact(&mut kind)?;
+        // Read through take, so a truncated file can't make the length from its header allocate anything
+        let mut data = Vec::new();
+        reader.by_ref().take(length as u64).read_to_end(&mut data)?;
+        if data.len() != length {
+            return Err(ViewerError::malformed("PNG file ends inside a chunk"));
+        }
+        // The CRC is not verified
+        let mut crc = [0; 4];
+        reader.read_exact(&mut crc)?;
+
+        Ok((kind, data))
+    }
+
+    // Keyword and text of a tEXt, zTXt or iTXt chunk. tEXt and zTXt are Latin-1, iTXt is UTF-8 and
+    // may be compressed. zTXt and iTXt have a compression method byte that has to be 0 (zlib)
+    fn read_text(kind: &[u8; 4], data: &[u8]) -> error::Result<(String, String)> {
+        let latin1 = |bytes: &[u8]| bytes.iter().map(|&byte| byte as char).collect::<String>();
+        let Some((keyword, rest)) = split_at_nul(data) else {
+            return Err(ViewerError::malformed("Text chunk without keyword"));
+        };
+        let inflate = |bytes: &[u8]| zlib_decompress(bytes, MAX_TEXT_LEN);
+
+        let text = match kind {
+            b"tEXt" => latin1(rest),
+            b"zTXt" => match rest {
+                [0, compressed @ ..] => latin1(&inflate(compressed)?),
+                _ => return Err(ViewerError::malformed("zTXt chunk without zlib compression"))
+            },
+            _ => {
+                let [compressed, 0, rest @ ..] = rest else {
+                    return Err(ViewerError::malformed("Invalid iTXt compression"));
+                };
+                // Language tag and translated keyword are skipped
+                let Some((_, rest)) = split_at_nul(rest).and_then(|(_, rest)| split_at_nul(rest)) else {
+                    return Err(ViewerError::malformed("iTXt chunk cut short"));
+                };
+                let text = if *compressed == 1 { inflate(rest)? } else { rest.to_vec() };
+                String::from_utf8_lossy(&text).into_owned()
+            }
+        };
+        Ok((latin1(keyword), text))
+    }
+
+    fn split_at_nul(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+        let nul = bytes.iter().position(|&byte| byte == 0)?;
+        Some((&bytes[..nul], &bytes[nul + 1..]))
+    }
+
+    // A file that ends before its IEND chunk is cut off rather than unreadable
+    fn truncated(err: ViewerError) -> ViewerError {
+        match err {
+            ViewerError::Io(err) if err.kind() == ErrorKind::UnexpectedEof => ViewerError::malformed("PNG file ends before its IEND chunk"),
+            err => err
+        }
+    }
+
+    fn read_pixels(data: &[u8], header: &Header, palette: &[Color], transparency: &[u8]) -> error::Result<Vec<Vec<Color>>> {
+        // Checked before allocating, the header alone may claim any size
+        if data.len() != header.data_len() {
+            return Err(ViewerError::malformed("PNG image data does not match the image size"));
+        }
+        let mut pixels = vec![vec![Color {red: 0, green: 0, blue: 0, alpha: 0}; header.width]; header.height];
+        // Filters operate on whole bytes, sub-byte pixels use the byte to the left
+        let bytes_per_pixel = std::cmp::max(header.bits_per_pixel() / 8, 1);
+
+        let mut pos = 0;
+        for ((x_start, y_start, x_step, y_step), pass_width, pass_height) in header.pass_sizes() {
+            let bytes_per_line = (pass_width * header.bits_per_pixel()).div_ceil(8);
+            let mut prev = vec![0; bytes_per_line];
+            for pass_y in 0..pass_height {
+                let line = &data[pos..pos + 1 + bytes_per_line];
+                pos += 1 + bytes_per_line;
+
+                let mut curr = line[1..].to_vec();
+                unfilter(line[0], &mut curr, &prev, bytes_per_pixel)?;
+                let y = y_start + pass_y * y_step;
+                for pass_x in 0..pass_width {
+                    pixels[y][x_start + pass_x * x_step] = to_color(&curr, pass_x, header, palette, transparency)?;
+                }
+                prev = curr;
+            }
+        }
+
+        Ok(pixels)
+    }
+
+    fn unfilter(filter: u8, line: &mut [u8], prev: &[u8], bytes_per_pixel: usize) -> error::Result<()> {
+        for i in 0..line.len() {
+            let left = if i >= bytes_per_pixel { line[i - bytes_per_pixel] } else { 0 };
+            let up = prev[i];
+            let up_left = if i >= bytes_per_pixel { prev[i - bytes_per_pixel] } else { 0 };
+            let predictor = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(ViewerError::malformed(format!("Invalid PNG filter type {filter}")))
+            };
+            line[i] = line[i].wrapping_add(predictor);
+        }
+
+        Ok(())
+    }
+
+    fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+        let estimate = left as i16 + up as i16 - up_left as i16;
+        let distance_left = (estimate - left as i16).abs();
+        let distance_up = (estimate - up as i16).abs();
+        let distance_up_left = (estimate - up_left as i16).abs();
+        if distance_left <= distance_up && distance_left <= distance_up_left {
+            left
+        } else if distance_up <= distance_up_left {
+            up
+        } else {
+            up_left
+        }
+    }
+
+    // Raw value of one channel, samples are big-endian and packed from the most significant bit
+    fn sample(line: &[u8], index: usize, bit_depth: u8) -> u16 {
+        match bit_depth {
+            16 => u16::from_be_bytes([line[2 * index], line[2 * index + 1]]),
+            8 => line[index] as u16,
+            depth => {
+                let bit = index * depth as usize;
+                let shift = 8 - depth as usize - bit % 8;
+                ((line[bit / 8] >> shift) & ((1 << depth) - 1)) as u16
+            }
+        }
+    }
+
+    fn to_color(line: &[u8], x: usize, header: &Header, palette: &[Color], transparency: &[u8]) -> error::Result<Color> {
+        let channels = header.channels();
+        let raw = |channel: usize| sample(line, x * channels + channel, header.bit_depth);
+        let scale = |value: u16| match header.bit_depth {
+            16 => scale_16_to_8(value),
+            8 => value as u8,
+            depth => (value as u32 * 255 / ((1 << depth) - 1)) as u8
+        };
+        // tRNS holds a single transparent gray value or RGB triple for images without alpha channel
+        let is_transparent = |values: &[u16]| {
+            transparency.len() == 2 * values.len()
+                && values.iter().enumerate().all(|(i, &value)| slice_to_usize_be(&transparency[2 * i..2 * i + 2]) == value as usize)
+        };
+
+        let color = match header.color_type {
+            GRAYSCALE => {
+                let gray = raw(0);
+                let alpha = if is_transparent(&[gray]) { 0 } else { 255 };
+                Color {red: scale(gray), green: scale(gray), blue: scale(gray), alpha}
+            },
+            TRUECOLOR => {
+                let (red, green, blue) = (raw(0), raw(1), raw(2));
+                let alpha = if is_transparent(&[red, green, blue]) { 0 } else { 255 };
+                Color {red: scale(red), green: scale(green), blue: scale(blue), alpha}
+            },
+            INDEXED => match palette.get(raw(0) as usize) {
+                Some(&color) => color,
+                None => return Err(ViewerError::malformed("Out-of-bounds palette index"))
+            },
+            GRAYSCALE_ALPHA => {
+                let gray = scale(raw(0));
+                Color {red: gray, green: gray, blue: gray, alpha: scale(raw(1))}
+            },
+            _ => Color {red: scale(raw(0)), green: scale(raw(1)), blue: scale(raw(2)), alpha: scale(raw(3))}
+        };
+
+        Ok(color)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // Stored deflate block in a zlib stream, the Adler-32 checksum is left zero as it is not verified
+        fn zlib_stored(data: &[u8]) -> Vec<u8> {
+            let len = data.len() as u16;
+            let mut stream = vec![0x78, 0x01, 0x01];
+            stream.extend_from_slice(&len.to_le_bytes());
+            stream.extend_from_slice(&(!len).to_le_bytes());
+            stream.extend_from_slice(data);
+            stream.extend_from_slice(&[0; 4]);
+            stream
+        }
+
+        // 8 bit true color image, CRCs are left zero as they are not verified
+        fn png(width: u32, height: u32, raw: &[u8]) -> Vec<u8> {
+            png_with(width, height, [8, TRUECOLOR, 0], &zlib_stored(raw))
+        }
+
+        // Image with the bit depth, color type and interlace method of format and the already compressed idat
+        fn png_with(width: u32, height: u32, format: [u8; 3], idat: &[u8]) -> Vec<u8> {
+            let mut bytes = SIGNATURE.to_vec();
+            let mut chunk = |kind: &[u8; 4], data: &[u8]| {
+                bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(kind);
+                bytes.extend_from_slice(data);
+                bytes.extend_from_slice(&[0; 4]);
+            };
+            let mut ihdr = Vec::new();
+            ihdr.extend_from_slice(&width.to_be_bytes());
+            ihdr.extend_from_slice(&height.to_be_bytes());
+            let [bit_depth, color_type, interlace] = format;
+            ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, interlace]);
+            chunk(b"IHDR", &ihdr);
+            chunk(b"IDAT", idat);
+            chunk(b"IEND", &[]);
+            bytes
+        }
+
+        #[test]
+        fn decodes_true_color() {
+            let bitmap = decode(&mut &png(2, 1, &[0, 255, 0, 0, 0, 0, 255])[..]).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![Color {red: 255, green: 0, blue: 0, alpha: 255}, Color {red: 0, green: 0, blue: 255, alpha: 255}]]);
+        }
+
+        #[test]
+        fn decodes_adam7_interlaced() {
+            // 3x3 8 bit grayscale with values (y * 3 + x) * 20 + 10, the lines of passes 1, 4, 5, 6 and 7 compressed by zlib
+            let idat = [0x78, 0xda, 0x63, 0xe0, 0x62, 0x30, 0x62, 0x68, 0x5a, 0xc5, 0x20, 0xc7, 0x30, 0x8d, 0xc1, 0x2d, 0x2a, 0x0f, 0x00,
+                0x13, 0xb5, 0x03, 0x2b];
+            let bitmap = decode(&mut &png_with(3, 3, [8, GRAYSCALE, 1], &idat)[..]).unwrap();
+            let gray = |value: u8| Color {red: value, green: value, blue: value, alpha: 255};
+            let expected: Vec<Vec<Color>> = (0..3).map(|y| (0..3).map(|x| gray((y * 3 + x) * 20 + 10)).collect()).collect();
+            assert_eq!(bitmap.pixels, expected);
+        }
+
+        #[test]
+        fn image_data_must_match_the_header() {
+            // One byte more or less than the 1 filter byte and 6 sample bytes of the line
+            assert!(matches!(decode(&mut &png(2, 1, &[0, 255, 0, 0, 0, 0, 255, 0])[..]), Err(ViewerError::Malformed {..})));
+            assert!(matches!(decode(&mut &png(2, 1, &[0, 255, 0, 0, 0, 0])[..]), Err(ViewerError::Malformed {..})));
+        }
+
+        #[test]
+        fn truncated_files_are_malformed() {
+            let bytes = png(2, 1, &[0, 255, 0, 0, 0, 0, 255]);
+            // Without IEND, inside the IDAT data, and a 20 byte file whose first chunk claims 2 GB
+            let iend_start = bytes.len() - 12;
+            for truncated in [&bytes[..iend_start], &bytes[..iend_start - 8]] {
+                assert!(matches!(decode(&mut &truncated[..]), Err(ViewerError::Malformed {..})));
+            }
+            let mut huge = SIGNATURE.to_vec();
+            huge.extend_from_slice(&[0x7f, 0xff, 0xff, 0xff, b'I', b'D', b'A', b'T', 0, 0, 0, 0]);
+            assert!(matches!(decode(&mut &huge[..]), Err(ViewerError::Malformed {..})));
+        }
+
+        #[test]
+        fn oversized_header_is_an_error() {
+            assert!(matches!(decode(&mut &png(0x7fffffff, 0x7fffffff, &[0])[..]), Err(ViewerError::Malformed {..})));
+        }
+
+        #[test]
+        fn text_chunks() {
+            assert_eq!(read_text(b"tEXt", b"Author\0Jos\xe9").unwrap(), ("Author".to_string(), "José".to_string()));
+
+            // zlib.compress(b"gray ramp, 16 steps")
+            let mut ztxt = b"Comment\0\0".to_vec();
+            ztxt.extend_from_slice(&[0x78, 0x9c, 0x4b, 0x2f, 0x4a, 0xac, 0x54, 0x28, 0x4a, 0xcc, 0x2d, 0xd0, 0x51, 0x30, 0x34, 0x53, 0x28,
+                0x2e, 0x49, 0x2d, 0x28, 0x06, 0x00, 0x41, 0xdd, 0x06, 0x86]);
+            assert_eq!(read_text(b"zTXt", &ztxt).unwrap(), ("Comment".to_string(), "gray ramp, 16 steps".to_string()));
+
+            // Uncompressed, with language tag and translated keyword
+            let itxt = "Title\0\0\0de\0Titel\0Grüße".as_bytes();
+            assert_eq!(read_text(b"iTXt", itxt).unwrap(), ("Title".to_string(), "Grüße".to_string()));
+
+            assert!(read_text(b"tEXt", b"no keyword").is_err());
+            assert!(read_text(b"iTXt", b"Title\0\x01").is_err());
+        }
+    }
+}
@@ -0,0 +1,153 @@
+pub mod qoi {
+    use std::io::{BufRead, Error, ErrorKind};
+
+    use crate::ansi::ansi::Color;
+    use crate::bitmap::bitmap::Bitmap;
+    use crate::common::common::{num_pixels, slice_to_usize_be};
+    use crate::error::error::{self, ViewerError};
+
+    const MAGIC: &[u8; 4] = b"qoif";
+    const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+    const OP_RGB: u8 = 0xfe;
+    const OP_RGBA: u8 = 0xff;
+    const OP_INDEX: u8 = 0b00;
+    const OP_DIFF: u8 = 0b01;
+    const OP_LUMA: u8 = 0b10;
+
+    fn hash(color: &Color) -> usize {
+        (color.red as usize * 3 + color.green as usize * 5 + color.blue as usize * 7 + color.alpha as usize * 11) % 64
+    }
+
+    pub fn decode<R: BufRead>(reader: &mut R) -> error::Result<Bitmap> {
+        let mut header = [0; 14];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Err(ViewerError::unsupported("File does not start with QOI magic values"));
+        }
+        let width = slice_to_usize_be(&header[4..8]);
+        let height = slice_to_usize_be(&header[8..12]);
+        // Channels and colorspace are informative only, the stream always decodes to RGBA
+        let _channels = header[12];
+        let _colorspace = header[13];
+        if width == 0 || height == 0 {
+            return Err(ViewerError::malformed(format!("Invalid QOI image size {width}x{height}")));
+        }
+        let Some(num_pixels) = num_pixels(width, height) else {
+            return Err(ViewerError::malformed("QOI image too large"));
+        };
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut bytes = data.iter().copied();
+        let mut next = || bytes.next().ok_or(Error::from(ErrorKind::UnexpectedEof));
+
+        let mut index = [Color {red: 0, green: 0, blue: 0, alpha: 0}; 64];
+        let mut color = Color {red: 0, green: 0, blue: 0, alpha: 255};
+        let mut colors = Vec::with_capacity(num_pixels);
+        while colors.len() < num_pixels {
+            let op = next()?;
+            let mut run = 1;
+            match op {
+                OP_RGB => {
+                    color.red = next()?;
+                    color.green = next()?;
+                    color.blue = next()?;
+                },
+                OP_RGBA => {
+                    color.red = next()?;
+                    color.green = next()?;
+                    color.blue = next()?;
+                    color.alpha = next()?;
+                },
+                _ => match op >> 6 {
+                    OP_INDEX => color = index[(op & 0x3f) as usize],
+                    // Differences to the previous pixel wrap around
+                    OP_DIFF => {
+                        color.red = color.red.wrapping_add((op >> 4) & 0x3).wrapping_sub(2);
+                        color.green = color.green.wrapping_add((op >> 2) & 0x3).wrapping_sub(2);
+                        color.blue = color.blue.wrapping_add(op & 0x3).wrapping_sub(2);
+                    },
+                    OP_LUMA => {
+                        let diff_green = (op & 0x3f).wrapping_sub(32);
+                        let diffs = next()?;
+                        color.red = color.red.wrapping_add(diff_green).wrapping_add(diffs >> 4).wrapping_sub(8);
+                        color.green = color.green.wrapping_add(diff_green);
+                        color.blue = color.blue.wrapping_add(diff_green).wrapping_add(diffs & 0xf).wrapping_sub(8);
+                    },
+                    _ => run = (op & 0x3f) as usize + 1
+                }
+            }
+
+            index[hash(&color)] = color;
+            let run = std::cmp::min(run, num_pixels - colors.len());
+            colors.extend(std::iter::repeat_n(color, run));
+        }
+
+        let mut end_marker = [0; 8];
+        for byte in end_marker.iter_mut() {
+            *byte = next()?;
+        }
+        if end_marker != END_MARKER {
+            return Err(ViewerError::malformed("QOI stream does not end with the end marker"));
+        }
+
+        let pixels = colors.chunks(width).map(|row| row.to_vec()).collect();
+        Ok(Bitmap {width, height, pixels})
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn qoi(width: u32, height: u32, ops: &[u8]) -> Vec<u8> {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&width.to_be_bytes());
+            bytes.extend_from_slice(&height.to_be_bytes());
+            bytes.extend_from_slice(&[4, 0]);
+            bytes.extend_from_slice(ops);
+            bytes.extend_from_slice(&END_MARKER);
+            bytes
+        }
+
+        #[test]
+        fn decodes_every_op() {
+            let ops = [
+                OP_RGB, 10, 20, 30,
+                // Diff of red +1, green -1, blue 0
+                0b01_11_01_10,
+                // Luma of green +5, red -2 and blue +3 relative to it
+                0b10_100101, 0x6b,
+                // Run of 2
+                0b11_000001,
+                OP_RGBA, 1, 2, 3, 4,
+                // Index of the first color, (10 * 3 + 20 * 5 + 30 * 7 + 255 * 11) % 64
+                9
+            ];
+            let bitmap = decode(&mut &qoi(7, 1, &ops)[..]).unwrap();
+            let color = |red: u8, green: u8, blue: u8, alpha: u8| Color {red, green, blue, alpha};
+            assert_eq!(bitmap.pixels, vec![vec![
+                color(10, 20, 30, 255),
+                color(11, 19, 30, 255),
+                color(14, 24, 38, 255),
+                color(14, 24, 38, 255),
+                color(14, 24, 38, 255),
+                color(1, 2, 3, 4),
+                color(10, 20, 30, 255)
+            ]]);
+        }
+
+        #[test]
+        fn differences_wrap_around() {
+            // From the initial black, a diff of -2 on every channel
+            let bitmap = decode(&mut &qoi(1, 1, &[0b01_00_00_00])[..]).unwrap();
+            assert_eq!(bitmap.pixels, vec![vec![Color {red: 254, green: 254, blue: 254, alpha: 255}]]);
+        }
+
+        #[test]
+        fn empty_dimensions_are_errors() {
+            assert!(matches!(decode(&mut &qoi(0, 4, &[])[..]), Err(ViewerError::Malformed {..})));
+            assert!(matches!(decode(&mut &qoi(4, 0, &[])[..]), Err(ViewerError::Malformed {..})));
+        }
+    }
+}
@@ -0,0 +1,140 @@
+pub mod quantize {
+    use std::collections::HashMap;
+
+    use crate::ansi::ansi::Color;
+
+    // Indices are stored as bytes
+    pub const MAX_PALETTE_SIZE: usize = 256;
+
+    // Distinct colors with the number of pixels they cover, alpha is ignored
+    struct ColorBox {
+        colors: Vec<([u8; 3], u32)>,
+        // Channel with the largest range of values and that range
+        widest: (usize, u8)
+    }
+
+    impl ColorBox {
+        fn new(colors: Vec<([u8; 3], u32)>) -> Self {
+            let range = |channel: usize| {
+                let values = colors.iter().map(|(color, _)| color[channel]);
+                values.clone().max().unwrap_or(0) - values.min().unwrap_or(0)
+            };
+            let widest = (0..3).map(|channel| (channel, range(channel))).max_by_key(|&(_, range)| range).unwrap_or((0, 0));
+            ColorBox {colors, widest}
+        }
+
+        // Splits at the pixel-weighted median of the widest channel
+        fn split(mut self) -> (ColorBox, ColorBox) {
+            let (channel, _) = self.widest;
+            // Ties are ordered by the whole color, so the split does not depend on the order of the colors
+            self.colors.sort_unstable_by_key(|&(color, _)| (color[channel], color));
+            let total: u64 = self.colors.iter().map(|&(_, count)| count as u64).sum();
+            let mut covered = 0;
+            let mut median = self.colors.len() - 1;
+            for (i, &(_, count)) in self.colors.iter().enumerate() {
+                covered += count as u64;
+                if covered * 2 >= total {
+                    median = i;
+                    break;
+                }
+            }
+            // Both halves keep at least one color
+            let at = std::cmp::min(median + 1, self.colors.len() - 1);
+            let upper = self.colors.split_off(at);
+            (ColorBox::new(self.colors), ColorBox::new(upper))
+        }
+
+        fn average(&self) -> Color {
+            let mut sums = [0u64; 3];
+            let mut total = 0u64;
+            for &(color, count) in &self.colors {
+                for channel in 0..3 {
+                    sums[channel] += color[channel] as u64 * count as u64;
+                }
+                total += count as u64;
+            }
+            let average = |sum: u64| ((sum + total / 2) / total) as u8;
+            Color {red: average(sums[0]), green: average(sums[1]), blue: average(sums[2]), alpha: 255}
+        }
+    }
+
+    // Median cut: the box with the widest channel is split until there are max_colors boxes, each box
+    // becomes the average of its colors. Images with at most max_colors distinct colors keep them exactly
+    pub fn quantize(pixels: &[Vec<Color>], max_colors: usize) -> (Vec<Color>, Vec<Vec<u8>>) {
+        let max_colors = max_colors.clamp(1, MAX_PALETTE_SIZE);
+        let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+        for pixel in pixels.iter().flatten() {
+            *counts.entry([pixel.red, pixel.green, pixel.blue]).or_insert(0) += 1;
+        }
+
+        // Sorted, as the iteration order of the map differs from run to run
+        let mut colors: Vec<([u8; 3], u32)> = counts.into_iter().collect();
+        colors.sort_unstable();
+        let mut boxes = vec![ColorBox::new(colors)];
+        while boxes.len() < max_colors {
+            let widest = boxes.iter().enumerate()
+                .filter(|(_, color_box)| color_box.colors.len() > 1)
+                .max_by_key(|(_, color_box)| color_box.widest.1)
+                .map(|(i, _)| i);
+            let Some(widest) = widest else {
+                break;
+            };
+            let (lower, upper) = boxes.swap_remove(widest).split();
+            boxes.push(lower);
+            boxes.push(upper);
+        }
+
+        let palette: Vec<Color> = boxes.iter().filter(|color_box| !color_box.colors.is_empty()).map(ColorBox::average).collect();
+        let mut nearest: HashMap<[u8; 3], u8> = HashMap::new();
+        let indices = pixels.iter().map(|row| row.iter().map(|pixel| {
+            *nearest.entry([pixel.red, pixel.green, pixel.blue]).or_insert_with(|| nearest_index(&palette, *pixel))
+        }).collect()).collect();
+
+        (palette, indices)
+    }
+
+    fn nearest_index(palette: &[Color], color: Color) -> u8 {
+        let distance = |other: &Color| {
+            let dr = color.red as i32 - other.red as i32;
+            let dg = color.green as i32 - other.green as i32;
+            let db = color.blue as i32 - other.blue as i32;
+            dr * dr + dg * dg + db * db
+        };
+        (0..palette.len()).min_by_key(|&i| distance(&palette[i])).unwrap_or(0) as u8
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn few_colors_round_trip_exactly() {
+            let color = |red, green, blue| Color {red, green, blue, alpha: 255};
+            let colors = [color(255, 0, 0), color(0, 128, 0), color(10, 20, 30), color(250, 250, 250), color(0, 0, 0)];
+            let pixels: Vec<Vec<Color>> = (0..4).map(|y| (0..6).map(|x| colors[(x + y) % colors.len()]).collect()).collect();
+
+            let (palette, indices) = quantize(&pixels, 8);
+            assert_eq!(palette.len(), colors.len());
+            for (row, row_indices) in pixels.iter().zip(&indices) {
+                for (pixel, &index) in row.iter().zip(row_indices) {
+                    assert_eq!(palette[index as usize], *pixel);
+                }
+            }
+        }
+
+        #[test]
+        fn palette_is_the_same_on_every_run() {
+            // More colors than palette entries, with few values per channel so the splits fall between tied colors
+            let mut seed = 1u32;
+            let mut next = || {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                ((seed >> 16) % 5 * 60) as u8
+            };
+            let pixels: Vec<Vec<Color>> = (0..24).map(|_| (0..24).map(|_| Color {red: next(), green: next(), blue: next(), alpha: 255}).collect()).collect();
+            let first = quantize(&pixels, 16);
+            for _ in 0..8 {
+                assert_eq!(quantize(&pixels, 16), first);
+            }
+        }
+    }
+}
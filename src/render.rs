@@ -0,0 +1,454 @@
+pub mod render {
+    use std::io::Write;
+
+    use crate::ansi::ansi::{self, Color, ColorMode, CursorPos, Erase, GammaTable};
+    use crate::term::term;
+
+    #[derive(Copy, Clone)]
+    pub enum RenderTarget {
+        Terminal,
+        // Runs the whole scaling/emission pipeline but discards the output
+        Null
+    }
+
+    // How pixels are put on the screen
+    #[derive(Copy, Clone)]
+    pub enum Backend {
+        // One colored block character per terminal cell
+        Blocks,
+        Sixel,
+        // Kitty graphics protocol
+        Kitty,
+        // Thresholded black and white, 2x4 dots per cell
+        Braille,
+        // One character per cell, picked from the ramp by its luma
+        Ascii
+    }
+
+    #[derive(Copy, Clone)]
+    pub enum Sampling {
+        // Area averaging when downscaling, nearest neighbor otherwise. Slideshows use nearest neighbor
+        // unless --fast is given, which keeps area averaging over the sparser samples
+        Auto,
+        Nearest,
+        Area
+    }
+
+    // How block character images are scaled to the terminal, none of them upscale vertically
+    #[derive(Copy, Clone)]
+    pub enum ScaleMode {
+        // Keeps the aspect ratio, the whole image is visible
+        Fit,
+        // Keeps the aspect ratio, the overflowing sides are cropped evenly
+        Fill,
+        // Scales each axis on its own to cover the terminal
+        Stretch
+    }
+
+    // Where the cursor is left once an image has been drawn
+    #[derive(Copy, Clone)]
+    pub enum CursorEnd {
+        Below,
+        Keep,
+        Home
+    }
+
+    // Cells at the terminal edges kept free for external UI like status bars
+    #[derive(Copy, Clone, Default, PartialEq)]
+    pub struct Inset {
+        pub top: usize,
+        pub right: usize,
+        pub bottom: usize,
+        pub left: usize
+    }
+
+    pub const DEFAULT_RAMP: &str = " .:-=+*#%@";
+
+    #[derive(Clone)]
+    pub struct RenderOptions {
+        pub target: RenderTarget,
+        pub backend: Backend,
+        pub sampling: Sampling,
+        pub scale: ScaleMode,
+        pub cursor: CursorEnd,
+        // Only every stride-th source pixel in each direction is considered when sampling
+        pub stride: usize,
+        pub inset: Inset,
+        // Scale to the terminal height keeping the aspect ratio, magnified by zoom, and show the
+        // term_height x term_width cells starting at y_offset, x_offset of the scaled image
+        pub fit_height: bool,
+        pub zoom: f64,
+        pub x_offset: usize,
+        pub y_offset: usize,
+        pub color_mode: ColorMode,
+        // Ordered dithering, only applied in 256 color mode
+        pub dither: bool,
+        pub grayscale: bool,
+        // Fully transparent cells of block character images show a checkerboard instead of their color
+        pub checkerboard: bool,
+        // Braille dots are set where the luma reaches it
+        pub threshold: u8,
+        // Characters from dark to bright, and whether they are drawn in the color of their cell
+        pub ramp: Vec<char>,
+        pub ascii_color: bool,
+        // Transparent pixels are composited over it, black if not given. Without it kitty keeps the transparency
+        pub background: Option<Color>,
+        // The color channels are already multiplied by the alpha
+        pub premultiplied: bool,
+        // Added to each channel, 0 keeps the image as is
+        pub brightness: f64,
+        // Factor for the distance of each channel from mid-gray, 1 keeps the image as is
+        pub contrast: f64,
+        pub gamma: Option<GammaTable>,
+        // Size of a terminal cell in pixels, asked from the terminal if not given
+        pub cell_size: Option<(usize, usize)>
+    }
+
+    impl Default for RenderOptions {
+        fn default() -> Self {
+            RenderOptions {
+                target: RenderTarget::Terminal,
+                backend: Backend::Blocks,
+                sampling: Sampling::Auto,
+                scale: ScaleMode::Fit,
+                cursor: CursorEnd::Below,
+                stride: 1,
+                inset: Inset::default(),
+                fit_height: false,
+                zoom: 1.0,
+                x_offset: 0,
+                y_offset: 0,
+                color_mode: ColorMode::TrueColor,
+                dither: false,
+                grayscale: false,
+                checkerboard: false,
+                threshold: 128,
+                ramp: DEFAULT_RAMP.chars().collect(),
+                ascii_color: false,
+                background: None,
+                premultiplied: false,
+                brightness: 0.0,
+                contrast: 1.0,
+                gamma: None,
+                cell_size: None
+            }
+        }
+    }
+
+    // Blanks the region of term_height x term_width cells at the inset row by row, the cells kept free by the inset
+    // are left alone. Without an inset the region is the whole screen
+    pub fn clear_region<W: Write>(writer: &mut W, term_height: usize, term_width: usize, inset: &Inset) -> std::io::Result<()> {
+        if *inset == Inset::default() {
+            return ansi::erase(Erase::Screen, writer);
+        }
+
+        for row in 0..term_height {
+            set_row(writer, row, inset)?;
+            ansi::erase_characters(term_width, writer)?;
+        }
+        Ok(())
+    }
+
+    // Moves to the start of a row of the region left free by the inset
+    pub fn set_row<W: Write>(writer: &mut W, row: usize, inset: &Inset) -> std::io::Result<()> {
+        ansi::set_cursor(CursorPos {x: inset.left + 1, y: inset.top + row + 1}, writer)
+    }
+
+    pub fn finish<W: Write>(writer: &mut W, rows_rendered: usize, options: &RenderOptions) -> std::io::Result<()> {
+        match options.cursor {
+            CursorEnd::Below => set_row(writer, rows_rendered, &options.inset),
+            CursorEnd::Keep => Ok(()),
+            CursorEnd::Home => ansi::reset_cursor(writer)
+        }
+    }
+
+    // Assumed when the terminal does not report its size in pixels
+    const DEFAULT_CELL_SIZE: (usize, usize) = (10, 20);
+
+    fn cell_size(options: &RenderOptions) -> (usize, usize) {
+        options.cell_size.or_else(term::cell_size).unwrap_or(DEFAULT_CELL_SIZE)
+    }
+
+    // Size of an image drawn at pixel resolution, step is the source pixels per drawn pixel in both directions
+    pub struct PixelFit {
+        pub step: f64,
+        pub width: usize,
+        pub height: usize,
+        // Terminal rows covered by the image
+        pub rows: usize
+    }
+
+    // Fits the image into the term_height x term_width cells keeping its aspect ratio, images are never upscaled
+    pub fn fit_pixels(width: usize, height: usize, term_height: usize, term_width: usize, options: &RenderOptions) -> PixelFit {
+        let (cell_width, cell_height) = cell_size(options);
+        let max_width = term_width * cell_width;
+        let max_height = term_height * cell_height;
+        let step = f64::max(f64::max(width as f64 / max_width as f64, height as f64 / max_height as f64), 1.0);
+        let scaled_height = std::cmp::max((height as f64 / step) as usize, 1);
+
+        PixelFit {
+            step,
+            width: std::cmp::max((width as f64 / step) as usize, 1),
+            height: scaled_height,
+            rows: scaled_height.div_ceil(cell_height)
+        }
+    }
+
+    // Cells are about twice as high as wide, so keeping the aspect ratio takes fewer source pixels per cell horizontally
+    fn cell_aspect(options: &RenderOptions) -> f64 {
+        let (cell_width, cell_height) = cell_size(options);
+        cell_width as f64 / cell_height as f64
+    }
+
+    // Cells drawn with one block character each, sampled from x_start, y_start on in steps of x_step, y_step source pixels
+    pub struct CellFit {
+        pub x_step: f64,
+        pub y_step: f64,
+        pub x_start: f64,
+        pub y_start: f64,
+        pub width: usize,
+        pub height: usize
+    }
+
+    pub fn fit_cells(width: usize, height: usize, term_height: usize, term_width: usize, options: &RenderOptions) -> CellFit {
+        let x_ratio = width as f64 / term_width as f64;
+        let y_ratio = height as f64 / term_height as f64;
+        let aspect = cell_aspect(options);
+        let (x_step, y_step, x_offset, y_offset) = if options.fit_height {
+            let step = fit_height_step(height, term_height, options.zoom);
+            (step * aspect, step, Some(options.x_offset), Some(options.y_offset))
+        } else {
+            let y_step = match options.scale {
+                ScaleMode::Fit => f64::max(f64::max(y_ratio, x_ratio / aspect), 1.0),
+                ScaleMode::Fill => f64::max(f64::min(y_ratio, x_ratio / aspect), 1.0),
+                ScaleMode::Stretch => {
+                    let (x_step, y_step) = (f64::max(x_ratio, 1.0), f64::max(y_ratio, 1.0));
+                    let (width, height) = (std::cmp::min(width, term_width), std::cmp::min(height, term_height));
+                    return CellFit {x_step, y_step, x_start: 0.0, y_start: 0.0, width, height};
+                }
+            };
+            (y_step * aspect, y_step, None, None)
+        };
+
+        let scaled_width = (width as f64 / x_step).ceil() as usize;
+        let scaled_height = (height as f64 / y_step).ceil() as usize;
+        // Without an explicit offset the overflow is cropped evenly from both sides
+        let offset = |offset: Option<usize>, max_offset: usize| offset.map_or(max_offset / 2, |offset| std::cmp::min(offset, max_offset));
+        let x_offset = offset(x_offset, scaled_width.saturating_sub(term_width));
+        let y_offset = offset(y_offset, scaled_height.saturating_sub(term_height));
+        CellFit {
+            x_step,
+            y_step,
+            x_start: x_offset as f64 * x_step,
+            y_start: y_offset as f64 * y_step,
+            width: std::cmp::min(scaled_width - x_offset, term_width),
+            height: std::cmp::min(scaled_height - y_offset, term_height)
+        }
+    }
+
+    // Cells left for the image once the inset is taken off the terminal
+    pub fn region_size(inset: &Inset) -> (usize, usize) {
+        let (term_height, term_width) = term::size();
+        (std::cmp::max(term_height.saturating_sub(inset.top + inset.bottom), 1), std::cmp::max(term_width.saturating_sub(inset.left + inset.right), 1))
+    }
+
+    // Inset of width x height cells centered in the region of term_height x term_width cells at the inset.
+    // An odd leftover row or column goes below or right of the image
+    pub fn center(width: usize, height: usize, term_height: usize, term_width: usize, inset: &Inset) -> Inset {
+        Inset {top: inset.top + (term_height - height) / 2, left: inset.left + (term_width - width) / 2, ..*inset}
+    }
+
+    // Source pixels per cell vertically, below 1 once zoomed in far enough
+    pub fn fit_height_step(height: usize, term_height: usize, zoom: f64) -> f64 {
+        f64::max((height as f64) / (term_height as f64), 1.0) / zoom
+    }
+
+    // Size in cells of the image scaled like in the fit_height case of fit_cells
+    pub fn fit_height_size(width: usize, height: usize, term_height: usize, options: &RenderOptions) -> (usize, usize) {
+        let step = fit_height_step(height, term_height, options.zoom);
+        (((width as f64) / (step * cell_aspect(options))).ceil() as usize, ((height as f64) / step).ceil() as usize)
+    }
+
+    const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+    // Roughly the distance between two levels of the 256 color cube
+    const DITHER_SPREAD: f64 = 40.0;
+
+    // Shifts the color by a threshold that depends on the cell position, so areas between two palette
+    // colors turn into a pattern of both instead of a hard band
+    pub fn dither(color: Color, x: usize, y: usize) -> Color {
+        let threshold = (BAYER_4X4[y % 4][x % 4] as f64 + 0.5) / 16.0 - 0.5;
+        let shift = |value: u8| (value as f64 + threshold * DITHER_SPREAD).round().clamp(0.0, 255.0) as u8;
+        Color {red: shift(color.red), green: shift(color.green), blue: shift(color.blue), alpha: color.alpha}
+    }
+
+    // Like the default background of most terminals
+    const DEFAULT_BACKGROUND: Color = Color {red: 0, green: 0, blue: 0, alpha: 255};
+
+    // Squares of CHECKER_SIZE x CHECKER_SIZE cells alternate between both shades
+    const CHECKER_SIZE: usize = 8;
+    const CHECKER_SHADES: [u8; 2] = [0x99, 0x66];
+
+    pub fn checkerboard(x: usize, y: usize) -> Color {
+        let shade = CHECKER_SHADES[(x / CHECKER_SIZE + y / CHECKER_SIZE) % 2];
+        Color {red: shade, green: shade, blue: shade, alpha: 255}
+    }
+
+    // Color of the terminal cell covering the source rectangle [x, x + x_step) x [y, y + y_step),
+    // with the color adjustments of the options applied
+    pub fn sample(pixels: &[Vec<Color>], x: f64, y: f64, x_step: f64, y_step: f64, options: &RenderOptions) -> Color {
+        adjust(average(pixels, x, y, x_step, y_step, options), options)
+    }
+
+    // Applied before quantizing to the output palette, so e.g. grayscale images use the gray ramp in 256 color mode
+    pub fn adjust(color: Color, options: &RenderOptions) -> Color {
+        let color = match (options.background, options.backend) {
+            // kitty composites itself and expects straight alpha
+            (None, Backend::Kitty) if options.premultiplied => color.unpremultiply(),
+            (None, Backend::Kitty) => color,
+            // Left for render_blocks to draw the checkerboard
+            _ if options.checkerboard && color.alpha == 0 => color,
+            (background, _) if options.premultiplied => color.composite_premultiplied_over(background.unwrap_or(DEFAULT_BACKGROUND)),
+            (background, _) => color.composite_over(background.unwrap_or(DEFAULT_BACKGROUND))
+        };
+        let color = if options.brightness != 0.0 || options.contrast != 1.0 {
+            color.adjust(options.brightness, options.contrast)
+        } else {
+            color
+        };
+        let color = match options.gamma {
+            Some(table) => table.apply(color),
+            None => color
+        };
+        if options.grayscale {
+            color.to_grayscale()
+        } else {
+            color
+        }
+    }
+
+    fn average(pixels: &[Vec<Color>], x: f64, y: f64, x_step: f64, y_step: f64, options: &RenderOptions) -> Color {
+        let x_start = x.floor() as usize;
+        let y_start = y.floor() as usize;
+        let area = match options.sampling {
+            Sampling::Auto => x_step > 1.0 || y_step > 1.0,
+            Sampling::Nearest => false,
+            Sampling::Area => true
+        };
+        // Snapped to the stride, so nearest neighbor also only shows every stride-th source pixel
+        if !area {
+            return pixels[y_start - y_start % options.stride][x_start - x_start % options.stride];
+        }
+
+        let y_end = std::cmp::min(std::cmp::max((y + y_step).floor() as usize, y_start + 1), pixels.len());
+        let x_end = std::cmp::min(std::cmp::max((x + x_step).floor() as usize, x_start + 1), pixels[y_start].len());
+        let (mut red, mut green, mut blue, mut alpha, mut count) = (0u32, 0u32, 0u32, 0u32, 0u32);
+        for row in pixels[y_start..y_end].iter().step_by(options.stride) {
+            for pixel in row[x_start..x_end].iter().step_by(options.stride) {
+                red += pixel.red as u32;
+                green += pixel.green as u32;
+                blue += pixel.blue as u32;
+                alpha += pixel.alpha as u32;
+                count += 1;
+            }
+        }
+
+        Color {
+            red: ((red + count / 2) / count) as u8,
+            green: ((green + count / 2) / count) as u8,
+            blue: ((blue + count / 2) / count) as u8,
+            alpha: ((alpha + count / 2) / count) as u8
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // Every pixel gets a distinct red value from its position
+        fn numbered(size: usize) -> Vec<Vec<Color>> {
+            (0..size).map(|y| (0..size).map(|x| Color {red: (y * size + x) as u8, green: 0, blue: 0, alpha: 255}).collect()).collect()
+        }
+
+        #[test]
+        fn stride_applies_to_nearest_neighbor() {
+            let pixels = numbered(8);
+            let options = RenderOptions {sampling: Sampling::Nearest, stride: 4, ..RenderOptions::default()};
+            assert_eq!(average(&pixels, 3.0, 6.0, 1.0, 1.0, &options).red, 32);
+            assert_eq!(average(&pixels, 5.0, 2.0, 1.0, 1.0, &options).red, 4);
+        }
+
+        #[test]
+        fn stride_applies_to_area_averaging() {
+            let pixels = numbered(8);
+            let options = RenderOptions {sampling: Sampling::Area, stride: 2, ..RenderOptions::default()};
+            // Pixels 0, 2, 16 and 18 of the 4x4 block
+            assert_eq!(average(&pixels, 0.0, 0.0, 4.0, 4.0, &options).red, 9);
+        }
+
+        fn checkerboard(size: usize) -> Vec<Vec<Color>> {
+            (0..size).map(|y| (0..size).map(|x| {
+                let value = if (x + y) % 2 == 0 { 0 } else { 255 };
+                Color {red: value, green: value, blue: value, alpha: 255}
+            }).collect()).collect()
+        }
+
+        #[test]
+        fn checkerboard_downscaled_by_four_is_flat_gray() {
+            let pixels = checkerboard(8);
+            let gray = Color {red: 128, green: 128, blue: 128, alpha: 255};
+            for sampling in [Sampling::Auto, Sampling::Area] {
+                let options = RenderOptions {sampling, ..RenderOptions::default()};
+                for (x, y) in [(0.0, 0.0), (4.0, 0.0), (0.0, 4.0), (4.0, 4.0)] {
+                    assert_eq!(average(&pixels, x, y, 4.0, 4.0, &options), gray);
+                }
+            }
+            // Nearest neighbor aliases into the same corner pixel of every cell
+            let options = RenderOptions {sampling: Sampling::Nearest, ..RenderOptions::default()};
+            assert_eq!(average(&pixels, 4.0, 0.0, 4.0, 4.0, &options).red, 0);
+        }
+
+        #[test]
+        fn dither_follows_the_bayer_matrix_on_a_ramp() {
+            // A smooth ramp, far enough from 0 and 255 that nothing is clamped
+            let ramp = |x: usize| Color {red: 60 + x as u8, green: 60 + x as u8, blue: 60 + x as u8, alpha: 255};
+            let mut total_shift = 0;
+            for y in 0..4 {
+                for x in 0..128 {
+                    let shifted = dither(ramp(x), x, y);
+                    let shift = shifted.red as i32 - ramp(x).red as i32;
+                    let expected = ((BAYER_4X4[y % 4][x % 4] as f64 + 0.5) / 16.0 - 0.5) * DITHER_SPREAD;
+                    assert_eq!(shift, expected.round() as i32);
+                    assert_eq!((shifted.green, shifted.blue, shifted.alpha), (shifted.red, shifted.red, 255));
+                    total_shift += shift;
+                }
+            }
+            // Every 4x4 tile averages out, so the ramp keeps its brightness
+            assert_eq!(total_shift, 0);
+        }
+
+        #[test]
+        fn fit_height_keeps_the_aspect_ratio() {
+            let options = RenderOptions {fit_height: true, zoom: 2.0, ..RenderOptions::default()};
+            let fit = fit_cells(400, 400, 20, 1000, &options);
+            assert_eq!(fit.y_step, 10.0);
+            assert_eq!(fit.x_step, 10.0 * cell_aspect(&options));
+            // The whole width fits, and pan limits are computed from the same size
+            assert_eq!((fit.width, 40), fit_height_size(400, 400, 20, &options));
+        }
+
+        #[test]
+        fn premultiplied_soft_edge_keeps_its_color() {
+            // White fading out over an edge, stored premultiplied so each channel equals the alpha
+            let edge: Vec<Color> = [0, 64, 128, 191, 255].iter().map(|&alpha| Color {red: alpha, green: alpha, blue: alpha, alpha}).collect();
+            let straight = RenderOptions::default();
+            let premultiplied = RenderOptions {premultiplied: true, ..RenderOptions::default()};
+            let on_white = RenderOptions {background: Some(Color {red: 255, green: 255, blue: 255, alpha: 255}), ..premultiplied.clone()};
+            for color in edge {
+                // Over black white with alpha a shows as a, straight compositing would darken it to a * a
+                assert_eq!(adjust(color, &premultiplied).red, color.alpha);
+                assert_eq!(adjust(color, &straight).red, ((color.alpha as u32 * color.alpha as u32 + 127) / 255) as u8);
+                assert_eq!(adjust(color, &on_white).red, 255);
+            }
+        }
+    }
+}
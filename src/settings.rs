@@ -0,0 +1,252 @@
+pub mod settings {
+    use std::env;
+    use std::fs::read_to_string;
+    use std::io::{Error, ErrorKind};
+    use std::path::PathBuf;
+
+    use crate::ansi::ansi::Color;
+    use crate::term::term::Key;
+
+    #[derive(Copy, Clone)]
+    pub enum Action {
+        Quit,
+        Up,
+        Down,
+        Left,
+        Right,
+        PageUp,
+        PageDown,
+        Top,
+        Bottom,
+        CopyColor,
+        ZoomIn,
+        ZoomOut
+    }
+
+    // Letter keys per action, arrow and page keys are always bound as well
+    #[derive(Copy, Clone)]
+    pub struct Keymap {
+        pub quit: u8,
+        pub up: u8,
+        pub down: u8,
+        pub left: u8,
+        pub right: u8,
+        pub page_up: u8,
+        pub page_down: u8,
+        pub top: u8,
+        pub bottom: u8,
+        pub copy_color: u8,
+        pub zoom_in: u8,
+        pub zoom_out: u8
+    }
+
+    impl Default for Keymap {
+        fn default() -> Self {
+            Keymap {
+                quit: b'q',
+                up: b'w',
+                down: b's',
+                left: b'a',
+                right: b'd',
+                page_up: b'b',
+                page_down: b'f',
+                top: b'g',
+                bottom: b'G',
+                copy_color: b'c',
+                zoom_in: b'+',
+                zoom_out: b'-'
+            }
+        }
+    }
+
+    impl Keymap {
+        pub fn action(&self, key: Option<Key>) -> Option<Action> {
+            let byte = match key? {
+                Key::Up => return Some(Action::Up),
+                Key::Down => return Some(Action::Down),
+                Key::Left => return Some(Action::Left),
+                Key::Right => return Some(Action::Right),
+                Key::PageUp => return Some(Action::PageUp),
+                Key::PageDown => return Some(Action::PageDown),
+                Key::Esc => return None,
+                Key::Char(byte) => byte
+            };
+
+            let bindings = [
+                (self.quit, Action::Quit),
+                (self.up, Action::Up),
+                (self.down, Action::Down),
+                (self.left, Action::Left),
+                (self.right, Action::Right),
+                (self.page_up, Action::PageUp),
+                (self.page_down, Action::PageDown),
+                (self.top, Action::Top),
+                (self.bottom, Action::Bottom),
+                (self.copy_color, Action::CopyColor),
+                (self.zoom_in, Action::ZoomIn),
+                (self.zoom_out, Action::ZoomOut)
+            ];
+            bindings.iter().find(|(bound, _)| *bound == byte).map(|&(_, action)| action)
+        }
+    }
+
+    #[derive(Copy, Clone)]
+    pub struct Settings {
+        pub keys: Keymap,
+        pub status_color: Color
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Settings {
+                keys: Keymap::default(),
+                status_color: Color {red: 0x44, green: 0x44, blue: 0x44, alpha: 255}
+            }
+        }
+    }
+
+    impl Settings {
+        // A missing file means defaults, a malformed one is reported and ignored as a whole
+        pub fn load() -> Self {
+            let Some(path) = config_path() else {
+                return Settings::default();
+            };
+            let contents = match read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(err) if err.kind() == ErrorKind::NotFound => return Settings::default(),
+                Err(err) => {
+                    eprintln!("Ignoring {}: {err}", path.display());
+                    return Settings::default();
+                }
+            };
+
+            match Settings::parse(&contents) {
+                Ok(settings) => settings,
+                Err(err) => {
+                    eprintln!("Ignoring {}: {err}", path.display());
+                    Settings::default()
+                }
+            }
+        }
+
+        // Subset of TOML: [section] headers, key = "value" pairs and # comments
+        fn parse(contents: &str) -> std::io::Result<Self> {
+            let mut settings = Settings::default();
+            let mut section = String::new();
+            for (i, line) in contents.lines().enumerate() {
+                let line_number = i + 1;
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                    section = name.trim().to_string();
+                    continue;
+                }
+
+                let Some((key, value)) = line.split_once('=') else {
+                    return Err(Error::other(format!("line {line_number}: expected key = \"value\"")));
+                };
+                let key = key.trim();
+                let value = parse_string(value.trim())
+                    .ok_or_else(|| Error::other(format!("line {line_number}: value of {key} must be a quoted string")))?;
+                settings.set(&section, key, value)
+                    .map_err(|err| Error::other(format!("line {line_number}: {err}")))?;
+            }
+
+            Ok(settings)
+        }
+
+        fn set(&mut self, section: &str, key: &str, value: &str) -> std::io::Result<()> {
+            match section {
+                "keys" => {
+                    let binding = match key {
+                        "quit" => &mut self.keys.quit,
+                        "up" => &mut self.keys.up,
+                        "down" => &mut self.keys.down,
+                        "left" => &mut self.keys.left,
+                        "right" => &mut self.keys.right,
+                        "page_up" => &mut self.keys.page_up,
+                        "page_down" => &mut self.keys.page_down,
+                        "top" => &mut self.keys.top,
+                        "bottom" => &mut self.keys.bottom,
+                        "copy_color" => &mut self.keys.copy_color,
+                        "zoom_in" => &mut self.keys.zoom_in,
+                        "zoom_out" => &mut self.keys.zoom_out,
+                        _ => return Err(Error::other(format!("unknown key binding {key}")))
+                    };
+                    match value.as_bytes() {
+                        &[byte] if byte.is_ascii_graphic() => *binding = byte,
+                        _ => return Err(Error::other(format!("{key} must be bound to a single printable ASCII character")))
+                    }
+                },
+                "colors" => match key {
+                    "status_bar" => self.status_color = Color::from_hex(value)?,
+                    _ => return Err(Error::other(format!("unknown color {key}")))
+                },
+                _ => return Err(Error::other(format!("unknown section [{section}]")))
+            }
+
+            Ok(())
+        }
+    }
+
+    // Quoted value, optionally followed by a comment
+    fn parse_string(value: &str) -> Option<&str> {
+        let rest = value.strip_prefix('"')?;
+        let (string, rest) = rest.split_once('"')?;
+        let rest = rest.trim();
+        if rest.is_empty() || rest.starts_with('#') {
+            Some(string)
+        } else {
+            None
+        }
+    }
+
+    fn config_path() -> Option<PathBuf> {
+        let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config")
+        };
+
+        Some(config_dir.join("term_viewer").join("config.toml"))
+    }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn error(contents: &str) -> String {
+            match Settings::parse(contents) {
+                Ok(_) => panic!("{contents:?} was accepted"),
+                Err(err) => err.to_string()
+            }
+        }
+
+        #[test]
+        fn parses_keys_and_colors() {
+            let contents = "# Vim-like\n[keys]\nup = \"k\"\ndown = \"j\"  # comment\n\n[colors]\nstatus_bar = \"#abc\"\n";
+            let settings = Settings::parse(contents).unwrap();
+            assert_eq!(settings.keys.up, b'k');
+            assert_eq!(settings.keys.down, b'j');
+            assert_eq!(settings.keys.quit, b'q');
+            assert_eq!(settings.status_color, Color {red: 0xaa, green: 0xbb, blue: 0xcc, alpha: 255});
+        }
+
+        #[test]
+        fn unknown_keys_are_errors() {
+            assert_eq!(error("[keys]\njump = \"x\""), "line 2: unknown key binding jump");
+            assert_eq!(error("[colors]\nheader = \"fff\""), "line 2: unknown color header");
+            assert_eq!(error("quit = \"x\""), "line 1: unknown section []");
+        }
+
+        #[test]
+        fn bad_values_are_errors() {
+            assert_eq!(error("[keys]\nquit = x"), "line 2: value of quit must be a quoted string");
+            assert_eq!(error("[keys]\nquit = \"xy\""), "line 2: quit must be bound to a single printable ASCII character");
+            assert!(Settings::parse("[keys]\nquit = \" \"").is_err());
+            assert!(Settings::parse("[keys]\nquit = \"x\" y").is_err());
+            assert!(Settings::parse("[colors]\nstatus_bar = \"12345\"").is_err());
+            assert_eq!(error("[keys]\nquit"), "line 2: expected key = \"value\"");
+        }
+    }
+}
@@ -0,0 +1,76 @@
+pub mod sixel {
+    use std::io::Write;
+
+    use crate::ansi::ansi::Color;
+    use crate::quantize::quantize::{quantize, MAX_PALETTE_SIZE};
+    use crate::render::render::{self, PixelFit, RenderOptions};
+
+    // Each sixel character encodes a column of 6 pixels
+    const BAND_HEIGHT: usize = 6;
+    const SIXEL_OFFSET: u8 = 63;
+
+    // Draws the image scaled to fit the region of term_height x term_width cells, returns the number of rows it covers
+    pub fn render<W: Write>(pixels: &[Vec<Color>], term_height: usize, term_width: usize, options: &RenderOptions, writer: &mut W) -> std::io::Result<usize> {
+        let height = pixels.len();
+        let width = pixels.first().map_or(0, Vec::len);
+        if width == 0 || height == 0 {
+            return Ok(0);
+        }
+
+        let PixelFit {step, width: out_width, height: out_height, rows} = render::fit_pixels(width, height, term_height, term_width, options);
+        let scaled: Vec<Vec<Color>> = (0..out_height).map(|y| {
+            (0..out_width).map(|x| render::sample(pixels, x as f64 * step, y as f64 * step, step, step, options)).collect()
+        }).collect();
+        // Every color register gets an entry of a palette adapted to the image
+        let (palette, indices) = quantize(&scaled, MAX_PALETTE_SIZE);
+
+        render::set_row(writer, 0, &options.inset)?;
+        // Pixel aspect ratio 1:1, followed by the image size
+        write!(writer, "\x1bPq\"1;1;{out_width};{out_height}")?;
+        // Sixel colors are given in percent
+        let percent = |value: u8| (value as usize * 100 + 127) / 255;
+        for (index, color) in palette.iter().enumerate() {
+            write!(writer, "#{index};2;{};{};{}", percent(color.red), percent(color.green), percent(color.blue))?;
+        }
+        for band in indices.chunks(BAND_HEIGHT) {
+            write_band(band, palette.len(), out_width, writer)?;
+        }
+        write!(writer, "\x1b\\")?;
+
+        Ok(rows)
+    }
+
+    // Every color of the band is drawn in its own pass over the columns, $ returns to the start of the band
+    fn write_band<W: Write>(band: &[Vec<u8>], num_colors: usize, width: usize, writer: &mut W) -> std::io::Result<()> {
+        let mut sixels: Vec<Option<Vec<u8>>> = vec![None; num_colors];
+        for (bit, row) in band.iter().enumerate() {
+            for (x, &index) in row.iter().enumerate() {
+                sixels[index as usize].get_or_insert_with(|| vec![0; width])[x] |= 1 << bit;
+            }
+        }
+
+        for (index, columns) in sixels.iter().enumerate() {
+            let Some(columns) = columns else {
+                continue;
+            };
+            let end = columns.iter().rposition(|&bits| bits != 0).map_or(0, |last| last + 1);
+            write!(writer, "#{index}")?;
+            let mut x = 0;
+            while x < end {
+                let bits = columns[x];
+                let run = columns[x..end].iter().take_while(|&&other| other == bits).count();
+                let character = (SIXEL_OFFSET + bits) as char;
+                // Repeats are only shorter from 4 characters on
+                if run > 3 {
+                    write!(writer, "!{run}{character}")?;
+                } else {
+                    write!(writer, "{}", character.to_string().repeat(run))?;
+                }
+                x += run;
+            }
+            write!(writer, "$")?;
+        }
+
+        write!(writer, "-")
+    }
+}
@@ -0,0 +1,265 @@
+pub mod term {
+    use std::cell::Cell;
+    use std::io::{stdout, Error, ErrorKind, Write};
+    use std::mem::MaybeUninit;
+    use std::panic;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Mutex, PoisonError};
+    use std::time::Duration;
+
+    use crate::ansi::ansi;
+
+    pub struct RawMode {
+        original: libc::termios
+    }
+
+    // Settings from before raw mode was enabled, for the panic hook
+    static SAVED_TERMIOS: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+    impl RawMode {
+        // Non-canonical, no echo. VMIN=0/VTIME=1 lets reads return after 100ms without input,
+        // so input loops neither block forever nor busy-spin
+        pub fn enable() -> std::io::Result<Self> {
+            let mut termios = MaybeUninit::uninit();
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+                return Err(Error::last_os_error());
+            }
+            let original = unsafe { termios.assume_init() };
+
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 1;
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+                return Err(Error::last_os_error());
+            }
+
+            SAVED_TERMIOS.lock().unwrap_or_else(PoisonError::into_inner).get_or_insert(original);
+            Ok(RawMode {original})
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+            *SAVED_TERMIOS.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        }
+    }
+
+    // The default hook prints the message while the terminal may still be in raw mode and on the alternate screen,
+    // so both are undone first. Dropping RawMode and AlternateScreen while unwinding comes too late for that
+    pub fn install_panic_hook() {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(original) = SAVED_TERMIOS.lock().unwrap_or_else(PoisonError::into_inner).take() {
+                unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original) };
+            }
+            let mut stdout = stdout();
+            let _ = ansi::show_cursor(&mut stdout);
+            let _ = stdout.flush();
+            ansi::leave_alternate_screen();
+            default_hook(info);
+        }));
+    }
+
+    // Used when stdout is not a terminal, e.g. when piping the output
+    const DEFAULT_SIZE: termsize::Size = termsize::Size {rows: 24, cols: 80};
+
+    // Terminal height and width in cells
+    pub fn size() -> (usize, usize) {
+        let size = termsize::get().unwrap_or(DEFAULT_SIZE);
+        (size.rows as usize, size.cols as usize)
+    }
+
+    // Set by the SIGWINCH handler, taken by the interactive loops to redraw at the new size
+    static RESIZED: AtomicBool = AtomicBool::new(false);
+
+    // Runs in signal context, so storing the flag is all it may do
+    extern "C" fn on_resize(_signal: libc::c_int) {
+        RESIZED.store(true, Ordering::Relaxed);
+    }
+
+    // Without SA_RESTART a pending read or poll is interrupted, so a resize is picked up right away instead of after the next key
+    pub fn watch_resize() -> std::io::Result<()> {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_resize as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        if unsafe { libc::sigaction(libc::SIGWINCH, &action, std::ptr::null_mut()) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn take_resize() -> bool {
+        RESIZED.swap(false, Ordering::Relaxed)
+    }
+
+    // Size of one character cell in pixels, if the terminal reports its size in pixels
+    pub fn cell_size() -> Option<(usize, usize)> {
+        let mut size = MaybeUninit::<libc::winsize>::uninit();
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        let size = unsafe { size.assume_init() };
+        if size.ws_col == 0 || size.ws_row == 0 || size.ws_xpixel < size.ws_col || size.ws_ypixel < size.ws_row {
+            return None;
+        }
+
+        Some(((size.ws_xpixel / size.ws_col) as usize, (size.ws_ypixel / size.ws_row) as usize))
+    }
+
+    // Time to wait for the terminal to answer a query
+    const QUERY_TIMEOUT_MS: i32 = 500;
+    const MAX_RESPONSE_LEN: usize = 64;
+
+    // Sends the query followed by a request for the primary device attributes (ESC [ ? Ps ; ... c).
+    // Every terminal answers the latter, so reading up to it collects the answer to the query without waiting for a timeout
+    fn query(request: &str) -> std::io::Result<Vec<u8>> {
+        let _raw_mode = RawMode::enable()?;
+        let mut stdout = stdout();
+        write!(stdout, "{request}\x1b[c")?;
+        stdout.flush()?;
+
+        let mut response = Vec::new();
+        while response.len() < MAX_RESPONSE_LEN && poll_stdin(QUERY_TIMEOUT_MS)? {
+            let Some(byte) = read_byte()? else {
+                break;
+            };
+            response.push(byte);
+            if byte == b'c' && device_attributes(&response).is_some() {
+                break;
+            }
+        }
+
+        Ok(response)
+    }
+
+    fn device_attributes(response: &[u8]) -> Option<&[u8]> {
+        let start = response.windows(3).position(|window| window == b"\x1b[?")?;
+        response[start + 3..].strip_suffix(b"c")
+    }
+
+    // The device attributes list 4 if the terminal can display Sixel graphics
+    pub fn supports_sixel() -> std::io::Result<bool> {
+        let response = query("")?;
+        let params = device_attributes(&response);
+        Ok(params.is_some_and(|params| params.split(|&byte| byte == b';').any(|param| param == b"4")))
+    }
+
+    // Terminals with the kitty graphics protocol acknowledge a query for a 1x1 test image
+    pub fn supports_kitty_graphics() -> std::io::Result<bool> {
+        let response = query("\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\")?;
+        Ok(response.windows(8).any(|window| window == b"_Gi=31;O"))
+    }
+
+    const ESC: u8 = 0x1b;
+    // Time to wait after ESC for the rest of an escape sequence before treating it as a lone ESC
+    const ESC_TIMEOUT_MS: i32 = 30;
+    const MAX_CSI_LEN: usize = 16;
+
+    thread_local! {
+        // Byte read while looking for an escape sequence that belongs to the next key
+        static PENDING: Cell<Option<u8>> = const { Cell::new(None) };
+    }
+
+    pub enum Key {
+        Char(u8),
+        Esc,
+        Up,
+        Down,
+        Right,
+        Left,
+        PageUp,
+        PageDown
+    }
+
+    pub fn read_key() -> std::io::Result<Option<Key>> {
+        let Some(byte) = read_byte()? else {
+            return Ok(None);
+        };
+        if byte != ESC {
+            return Ok(Some(Key::Char(byte)));
+        }
+
+        if !poll_stdin(ESC_TIMEOUT_MS)? {
+            return Ok(Some(Key::Esc));
+        }
+        match read_byte()? {
+            Some(b'[') => read_csi(),
+            next => {
+                PENDING.set(next);
+                Ok(Some(Key::Esc))
+            }
+        }
+    }
+
+    // Waits at most timeout for a key press instead of the fixed VTIME interval, so playback loops keep their pace
+    pub fn read_key_timeout(timeout: Duration) -> std::io::Result<Option<Key>> {
+        let timeout_ms = std::cmp::min(timeout.as_micros().div_ceil(1000), i32::MAX as u128) as i32;
+        if PENDING.get().is_none() && !poll_stdin(timeout_ms)? {
+            return Ok(None);
+        }
+
+        read_key()
+    }
+
+    // Consumes a whole CSI sequence so unknown ones are dropped instead of leaking as key presses
+    fn read_csi() -> std::io::Result<Option<Key>> {
+        let mut params = Vec::new();
+        for _ in 0..MAX_CSI_LEN {
+            let Some(byte) = read_byte()? else {
+                return Ok(None);
+            };
+
+            match byte {
+                b'A' => return Ok(Some(Key::Up)),
+                b'B' => return Ok(Some(Key::Down)),
+                b'C' => return Ok(Some(Key::Right)),
+                b'D' => return Ok(Some(Key::Left)),
+                b'~' => return Ok(match &params[..] {
+                    b"5" => Some(Key::PageUp),
+                    b"6" => Some(Key::PageDown),
+                    _ => None
+                }),
+                0x40..=0x7e => return Ok(None),
+                _ => params.push(byte)
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Reads straight from the fd, std's buffered stdin would hide pending bytes from poll
+    fn read_byte() -> std::io::Result<Option<u8>> {
+        if let Some(byte) = PENDING.take() {
+            return Ok(Some(byte));
+        }
+
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) };
+        match n {
+            // Interrupted by a signal such as SIGWINCH, handled like a read without input
+            n if n < 0 => {
+                let err = Error::last_os_error();
+                if err.kind() == ErrorKind::Interrupted { Ok(None) } else { Err(err) }
+            },
+            0 => Ok(None),
+            _ => Ok(Some(byte))
+        }
+    }
+
+    fn poll_stdin(timeout_ms: i32) -> std::io::Result<bool> {
+        let mut fd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0
+        };
+        let ret = unsafe { libc::poll(&mut fd, 1, timeout_ms) };
+        if ret < 0 {
+            let err = Error::last_os_error();
+            return if err.kind() == ErrorKind::Interrupted { Ok(false) } else { Err(err) };
+        }
+
+        Ok(ret > 0)
+    }
+}
@@ -0,0 +1,333 @@
+pub mod tga {
+    use std::io::{BufRead, Read};
+
+    use crate::ansi::ansi::Color;
+    use crate::bitmap::bitmap::Bitmap;
+    use crate::common::common::{num_pixels, read_u16, slice_to_usize_le};
+    use crate::error::error::{self, ViewerError};
+
+    const RLE_FLAG: u8 = 0x8;
+    const TOP_TO_BOTTOM: u8 = 0x20;
+    const RIGHT_TO_LEFT: u8 = 0x10;
+    // Number of attribute bits per pixel, i.e. alpha
+    const ATTRIBUTE_BITS: u8 = 0x0f;
+    // TGA 2.0 files end with the offset of their extension area, that of the developer area and this signature
+    const FOOTER_SIGNATURE: &[u8; 18] = b"TRUEVISION-XFILE.\0";
+    const FOOTER_SIZE: usize = 8 + FOOTER_SIGNATURE.len();
+    // Offset of the attributes type within the extension area, whose value PREMULTIPLIED marks premultiplied alpha
+    const ATTRIBUTES_TYPE_OFFSET: usize = 494;
+    const PREMULTIPLIED: u8 = 4;
+
+    enum ImageKind {
+        ColorMapped,
+        TrueColor,
+        Grayscale
+    }
+
+    struct Header {
+        id_length: u8,
+        color_map_type: u8,
+        image_type: u8,
+        color_map_first_entry: u16,
+        color_map_length: u16,
+        color_map_entry_size: u8,
+        width: u16,
+        height: u16,
+        pixel_depth: u8,
+        image_descriptor: u8
+    }
+
+    impl Header {
+        fn from_reader<R: BufRead>(reader: &mut R) -> error::Result<Self> {
+            let mut bytes = [0; 3];
+            reader.read_exact(&mut bytes)?;
+            let [id_length, color_map_type, image_type] = bytes;
+            let color_map_first_entry = read_u16(reader)?;
+            let color_map_length = read_u16(reader)?;
+            let mut color_map_entry_size = [0; 1];
+            reader.read_exact(&mut color_map_entry_size)?;
+            let _x_origin = read_u16(reader)?;
+            let _y_origin = read_u16(reader)?;
+            let width = read_u16(reader)?;
+            let height = read_u16(reader)?;
+            let mut bytes = [0; 2];
+            reader.read_exact(&mut bytes)?;
+            let [pixel_depth, image_descriptor] = bytes;
+
+            Ok(Header {
+                id_length,
+                color_map_type,
+                image_type,
+                color_map_first_entry,
+                color_map_length,
+                color_map_entry_size: color_map_entry_size[0],
+                width,
+                height,
+                pixel_depth,
+                image_descriptor
+            })
+        }
+
+        fn kind(&self) -> error::Result<ImageKind> {
+            match self.image_type & !RLE_FLAG {
+                1 => Ok(ImageKind::ColorMapped),
+                2 => Ok(ImageKind::TrueColor),
+                3 => Ok(ImageKind::Grayscale),
+                _ => Err(ViewerError::unsupported(format!("Unsupported TGA image type {}", self.image_type)))
+            }
+        }
+
+        fn is_rle(&self) -> bool {
+            self.image_type & RLE_FLAG != 0
+        }
+
+        // Without attribute bits the top byte of 32 bit values is unused rather than alpha
+        fn has_alpha(&self) -> bool {
+            self.image_descriptor & ATTRIBUTE_BITS != 0
+        }
+    }
+
+    pub fn decode<R: BufRead>(reader: &mut R) -> error::Result<Bitmap> {
+        // Read whole, as the footer at the end tells how the alpha of the pixels before it is stored
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let premultiplied = is_premultiplied(&bytes);
+        let reader = &mut &bytes[..];
+
+        let header = Header::from_reader(reader)?;
+        let kind = header.kind()?;
+
+        let mut image_id = vec![0; header.id_length as usize];
+        reader.read_exact(&mut image_id)?;
+
+        let color_map = if header.color_map_type == 1 {
+            read_color_map(reader, &header)?
+        } else {
+            Vec::new()
+        };
+
+        let width = header.width as usize;
+        let height = header.height as usize;
+        if width == 0 || height == 0 {
+            return Err(ViewerError::malformed(format!("Invalid TGA image size {width}x{height}")));
+        }
+        let Some(num_pixels) = num_pixels(width, height) else {
+            return Err(ViewerError::malformed("TGA image too large"));
+        };
+        let values = read_pixel_values(reader, &header, num_pixels)?;
+        let mut colors = Vec::with_capacity(values.len());
+        for value in values {
+            let color = match kind {
+                ImageKind::ColorMapped => {
+                    let index = (value as usize).checked_sub(header.color_map_first_entry as usize);
+                    match index.and_then(|index| color_map.get(index)) {
+                        Some(&color) => color,
+                        None => return Err(ViewerError::malformed("Color map index out of range"))
+                    }
+                },
+                ImageKind::TrueColor => to_color(value, header.pixel_depth, header.has_alpha())?,
+                // 16 bit grayscale carries alpha in the high byte
+                ImageKind::Grayscale => {
+                    let gray = (value & 0xff) as u8;
+                    Color {red: gray, green: gray, blue: gray, alpha: 255}
+                }
+            };
+            colors.push(if premultiplied { color.unpremultiply() } else { color });
+        }
+
+        let mut pixels: Vec<Vec<Color>> = colors.chunks(width).map(|row| row.to_vec()).collect();
+        if header.image_descriptor & RIGHT_TO_LEFT != 0 {
+            pixels.iter_mut().for_each(|row| row.reverse());
+        }
+        if header.image_descriptor & TOP_TO_BOTTOM == 0 {
+            pixels.reverse();
+        }
+
+        Ok(Bitmap {width, height, pixels})
+    }
+
+    fn is_premultiplied(bytes: &[u8]) -> bool {
+        let Some(footer) = bytes.len().checked_sub(FOOTER_SIZE).map(|start| &bytes[start..]) else {
+            return false;
+        };
+        if !footer.ends_with(FOOTER_SIGNATURE) {
+            return false;
+        }
+        // An extension offset of 0 means there is no extension area
+        let extension_offset = slice_to_usize_le(&footer[..4]);
+        extension_offset != 0 && bytes.get(extension_offset + ATTRIBUTES_TYPE_OFFSET) == Some(&PREMULTIPLIED)
+    }
+
+    fn read_color_map<R: BufRead>(reader: &mut R, header: &Header) -> error::Result<Vec<Color>> {
+        let bytes_per_entry = (header.color_map_entry_size as usize).div_ceil(8);
+        let mut color_map = Vec::new();
+        for _ in 0..header.color_map_length {
+            let value = read_value(reader, bytes_per_entry)?;
+            color_map.push(to_color(value, header.color_map_entry_size, header.has_alpha())?);
+        }
+
+        Ok(color_map)
+    }
+
+    // Raw little-endian pixel values. RLE packets may span scanlines, so they are
+    // expanded into one stream and only split into rows afterwards
+    fn read_pixel_values<R: BufRead>(reader: &mut R, header: &Header, num_pixels: usize) -> error::Result<Vec<u32>> {
+        let bytes_per_pixel = (header.pixel_depth as usize).div_ceil(8);
+        if bytes_per_pixel == 0 || bytes_per_pixel > 4 {
+            return Err(ViewerError::unsupported(format!("Unsupported TGA pixel depth {}", header.pixel_depth)));
+        }
+
+        let mut values = Vec::with_capacity(num_pixels);
+        if !header.is_rle() {
+            for _ in 0..num_pixels {
+                values.push(read_value(reader, bytes_per_pixel)?);
+            }
+            return Ok(values);
+        }
+
+        while values.len() < num_pixels {
+            let mut packet = [0; 1];
+            reader.read_exact(&mut packet)?;
+            let count = std::cmp::min((packet[0] & 0x7f) as usize + 1, num_pixels - values.len());
+            if packet[0] & 0x80 != 0 {
+                let value = read_value(reader, bytes_per_pixel)?;
+                values.extend(std::iter::repeat_n(value, count));
+            } else {
+                for _ in 0..count {
+                    values.push(read_value(reader, bytes_per_pixel)?);
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
+    fn read_value<R: BufRead>(reader: &mut R, num_bytes: usize) -> error::Result<u32> {
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf[..num_bytes])?;
+        Ok(slice_to_usize_le(&buf[..num_bytes]) as u32)
+    }
+
+    fn to_color(value: u32, depth: u8, has_alpha: bool) -> error::Result<Color> {
+        match depth {
+            // ARGB1555, where a cleared attribute bit marks a transparent pixel
+            15 | 16 => {
+                let expand = |c: u32| ((c << 3) | (c >> 2)) as u8;
+                Ok(Color {
+                    red: expand((value >> 10) & 0x1f),
+                    green: expand((value >> 5) & 0x1f),
+                    blue: expand(value & 0x1f),
+                    alpha: if has_alpha && value & 0x8000 == 0 { 0 } else { 255 }
+                })
+            },
+            24 => Ok(Color::from(value)),
+            32 => {
+                let alpha = if has_alpha { (value >> 24) as u8 } else { 255 };
+                Ok(Color {alpha, ..Color::from(value)})
+            },
+            _ => Err(ViewerError::unsupported(format!("Unsupported TGA color depth {depth}")))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // Uncompressed or RLE true color image, stored top to bottom
+        fn tga(width: u16, height: u16, pixel_depth: u8, image_descriptor: u8, rle: bool, data: &[u8]) -> Vec<u8> {
+            let mut bytes = vec![0, 0, if rle { 2 | RLE_FLAG } else { 2 }, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+            bytes.extend_from_slice(&width.to_le_bytes());
+            bytes.extend_from_slice(&height.to_le_bytes());
+            bytes.extend_from_slice(&[pixel_depth, image_descriptor | TOP_TO_BOTTOM]);
+            bytes.extend_from_slice(data);
+            bytes
+        }
+
+        #[test]
+        fn rle_packets_span_rows() {
+            // A run of 3 followed by a raw packet of 3, each crossing the end of a 2 pixel row
+            let data = [0x82, 0x00, 0x00, 0xff, 0x02, 0x00, 0xff, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff];
+            let bitmap = decode(&mut &tga(2, 3, 24, 0, true, &data)[..]).unwrap();
+            let red = Color {red: 255, green: 0, blue: 0, alpha: 255};
+            let green = Color {red: 0, green: 255, blue: 0, alpha: 255};
+            let blue = Color {red: 0, green: 0, blue: 255, alpha: 255};
+            assert_eq!(bitmap.pixels, vec![vec![red, red], vec![red, green], vec![blue, red]]);
+        }
+
+        #[test]
+        fn alpha_from_attribute_bits() {
+            let data = [0x10, 0x20, 0x30, 0x80];
+            let bitmap = decode(&mut &tga(1, 1, 32, 8, false, &data)[..]).unwrap();
+            assert_eq!(bitmap.pixels[0][0], Color {red: 0x30, green: 0x20, blue: 0x10, alpha: 0x80});
+
+            // No attribute bits, the top byte is not alpha
+            let bitmap = decode(&mut &tga(1, 1, 32, 0, false, &data)[..]).unwrap();
+            assert_eq!(bitmap.pixels[0][0].alpha, 255);
+        }
+
+        #[test]
+        fn alpha_from_the_attribute_bit() {
+            // Opaque red, then transparent blue
+            let data = [0x00, 0xfc, 0x1f, 0x00];
+            let bitmap = decode(&mut &tga(2, 1, 16, 1, false, &data)[..]).unwrap();
+            assert_eq!(bitmap.pixels[0], vec![
+                Color {red: 255, green: 0, blue: 0, alpha: 255},
+                Color {red: 0, green: 0, blue: 255, alpha: 0}
+            ]);
+
+            // Without attribute bits both are opaque
+            let bitmap = decode(&mut &tga(2, 1, 16, 0, false, &data)[..]).unwrap();
+            assert_eq!(bitmap.pixels[0][1].alpha, 255);
+        }
+
+        #[test]
+        fn color_mapped_rle() {
+            // Type 9 with a 2 entry 24 bit color map starting at entry 1, stored bottom to top
+            let mut bytes = vec![0, 1, 1 | RLE_FLAG, 1, 0, 2, 0, 24, 0, 0, 0, 0, 3, 0, 2, 0, 8, 0];
+            bytes.extend_from_slice(&[0x00, 0x00, 0xff, 0xff, 0x00, 0x00]);
+            // A run of 4 of entry 1, then 2 raw indices
+            bytes.extend_from_slice(&[0x83, 0x01, 0x01, 0x02, 0x01]);
+            let bitmap = decode(&mut &bytes[..]).unwrap();
+            let red = Color {red: 255, green: 0, blue: 0, alpha: 255};
+            let blue = Color {red: 0, green: 0, blue: 255, alpha: 255};
+            assert_eq!(bitmap.pixels, vec![vec![red, blue, red], vec![red, red, red]]);
+
+            // Index 0 lies before the first entry
+            let last = bytes.len() - 1;
+            bytes[last] = 0;
+            assert!(matches!(decode(&mut &bytes[..]), Err(ViewerError::Malformed {..})));
+        }
+
+        #[test]
+        fn empty_dimensions_are_errors() {
+            assert!(matches!(decode(&mut &tga(0, 4, 24, 0, false, &[])[..]), Err(ViewerError::Malformed {..})));
+            assert!(matches!(decode(&mut &tga(4, 0, 24, 0, false, &[])[..]), Err(ViewerError::Malformed {..})));
+        }
+
+        #[test]
+        fn oversized_header_is_an_error() {
+            assert!(matches!(decode(&mut &tga(65535, 65535, 24, 0, false, &[])[..]), Err(ViewerError::Malformed {..})));
+        }
+
+        #[test]
+        fn premultiplied_alpha_from_the_extension_area() {
+            // Half transparent white, premultiplied to 0x80
+            let mut bytes = tga(1, 1, 32, 8, false, &[0x80, 0x80, 0x80, 0x80]);
+            let extension_offset = bytes.len() as u32;
+            let mut extension = vec![0; ATTRIBUTES_TYPE_OFFSET + 1];
+            extension[ATTRIBUTES_TYPE_OFFSET] = PREMULTIPLIED;
+            bytes.extend_from_slice(&extension);
+            bytes.extend_from_slice(&extension_offset.to_le_bytes());
+            bytes.extend_from_slice(&[0; 4]);
+            bytes.extend_from_slice(FOOTER_SIGNATURE);
+            let bitmap = decode(&mut &bytes[..]).unwrap();
+            assert_eq!(bitmap.pixels[0][0], Color {red: 255, green: 255, blue: 255, alpha: 0x80});
+
+            // Other attribute types keep the channels as they are
+            let attributes_type = bytes.len() - FOOTER_SIZE - 1;
+            bytes[attributes_type] = 3;
+            let bitmap = decode(&mut &bytes[..]).unwrap();
+            assert_eq!(bitmap.pixels[0][0], Color {red: 0x80, green: 0x80, blue: 0x80, alpha: 0x80});
+        }
+    }
+}